@@ -0,0 +1,10 @@
+#![no_main]
+use libfuzzer_sys::fuzz_target;
+
+// Loading must turn any input into either a VM or a LoadError, never a panic.
+fuzz_target!(|data: &[u8]| {
+    if let Ok(vm) = rick::VM::new(data) {
+        let _ = vm.verify();
+    }
+    let _ = rick::disasm::disassemble(data);
+});
@@ -0,0 +1,528 @@
+extern crate serde_json;
+use serde_json::json;
+
+use std::collections::HashMap;
+use std::fmt;
+
+use crate::vm::{Container, DebugInfo, Location, Obj, Pool, FORMAT_VERSION};
+use crate::vm::op::INSTRUCTION_SET;
+
+/// AsmError points at the source line the assembler choked on.
+#[derive(Clone, Debug, PartialEq)]
+pub struct AsmError {
+    pub line: usize,
+    pub msg: String,
+}
+
+impl fmt::Display for AsmError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "line {}: {}", self.line, self.msg)
+    }
+}
+
+type AsmResult<T> = Result<T, AsmError>;
+
+const PRODUCER: &str = concat!("rick asm ", env!("CARGO_PKG_VERSION"));
+
+fn fail<T>(line: usize, msg: String) -> AsmResult<T> {
+    Err(AsmError { line, msg })
+}
+
+enum Operand {
+    Name(String),
+    Literal(Obj),
+}
+
+enum Item {
+    Instruction { opcode: u8, operand: Option<Operand> },
+    Byte(u8),
+}
+
+/// Assemble SmallO assembly text into an executable.
+///
+/// Besides the mnemonics from the README the assembler understands:
+///
+/// - `name:` defines a label at the current offset;
+/// - `.mem name literal` declares a named memory slot with an initial value;
+/// - `.byte 0xNN` emits a raw byte;
+/// - `.loc "file" LINE [COLUMN]` attributes the code that follows to a place
+///   in another source file, for compilers that emit assembly;
+/// - literal operands like `push 42` or `push "hi"`, which get a constant
+///   memory slot of their own.
+///
+/// Declared slots come first in memory, in declaration order. Labels used as
+/// operands and literals get slots appended after them, and any other unknown
+/// name becomes a new variable initialized to null.
+pub fn assemble(src: &str) -> AsmResult<Vec<u8>> {
+    run(Assembler::new(None), src)
+}
+
+/// Assemble like `assemble`, but also record debug info: where each
+/// instruction came from, the names of memory slots and the labels. Code not
+/// covered by a `.loc` directive is attributed to its line in `file`.
+pub fn assemble_with_debug_info(src: &str, file: &str)
+    -> AsmResult<Vec<u8>> {
+    run(Assembler::new(Some(file)), src)
+}
+
+fn run(mut asm: Assembler, src: &str) -> AsmResult<Vec<u8>> {
+    for (i, line) in src.lines().enumerate() {
+        asm.parse_line(i + 1, line)?;
+    }
+    asm.emit()
+}
+
+struct Assembler {
+    items: Vec<(usize, Item)>,
+    offset: usize,
+    labels: HashMap<String, usize>,
+    mem: Vec<Obj>,
+    slots: HashMap<String, usize>,
+    consts: HashMap<String, usize>,
+    debug: Option<DebugInfo>,
+    loc: Option<(usize, usize, usize)>,
+}
+
+impl Assembler {
+    fn new(file: Option<&str>) -> Self {
+        Self {
+            items: Vec::new(),
+            offset: 0,
+            labels: HashMap::new(),
+            mem: Vec::new(),
+            slots: HashMap::new(),
+            consts: HashMap::new(),
+            debug: file.map(|file| DebugInfo {
+                files: vec![file.to_string()],
+                ..DebugInfo::default()
+            }),
+            loc: None,
+        }
+    }
+
+    fn name_taken(&self, name: &str) -> bool {
+        self.labels.contains_key(name) || self.slots.contains_key(name)
+    }
+
+    fn parse_line(&mut self, line: usize, raw: &str) -> AsmResult<()> {
+        let mut text = strip_comment(raw).trim();
+
+        if let Some((label, rest)) = split_label(text) {
+            if self.name_taken(label) {
+                return fail(line, format!("duplicate name {}", label));
+            }
+            self.labels.insert(label.to_string(), self.offset);
+            text = rest.trim();
+        }
+        if text.is_empty() {
+            return Ok(());
+        }
+
+        let (word, rest) = split_word(text);
+        match word {
+            ".mem" => self.parse_mem(line, rest),
+            ".loc" => self.parse_loc(line, rest),
+            ".byte" => {
+                self.locate(line, column(raw, text));
+                self.parse_byte(line, rest)
+            },
+            _ => {
+                self.locate(line, column(raw, text));
+                self.parse_instruction(line, word, rest)
+            },
+        }
+    }
+
+    // Record the source position of the item about to be emitted.
+    fn locate(&mut self, line: usize, column: usize) {
+        let offset = self.offset;
+        if let Some(debug) = self.debug.as_mut() {
+            let (file, line, column) = self.loc.unwrap_or((0, line, column));
+            debug.locations.push(Location { offset, file, line, column });
+        }
+    }
+
+    fn parse_loc(&mut self, line: usize, rest: &str) -> AsmResult<()> {
+        let invalid = || fail(line, format!("invalid location {:?}", rest));
+
+        let mut values = serde_json::Deserializer::from_str(rest)
+            .into_iter::<String>();
+        let file = match values.next() {
+            Some(Ok(file)) => file,
+            _ => return invalid(),
+        };
+        let numbers: Result<Vec<usize>, _> = rest[values.byte_offset()..]
+            .split_whitespace()
+            .map(str::parse)
+            .collect();
+        let (src_line, src_column) = match numbers.as_deref() {
+            Ok([l]) => (*l, 1),
+            Ok([l, c]) => (*l, *c),
+            _ => return invalid(),
+        };
+
+        if let Some(debug) = self.debug.as_mut() {
+            let index = match debug.files.iter().position(|f| *f == file) {
+                Some(index) => index,
+                None => {
+                    debug.files.push(file);
+                    debug.files.len() - 1
+                },
+            };
+            self.loc = Some((index, src_line, src_column));
+        }
+        Ok(())
+    }
+
+    fn parse_mem(&mut self, line: usize, rest: &str) -> AsmResult<()> {
+        let (name, literal) = split_word(rest);
+        if !is_ident(name) {
+            return fail(line, format!("invalid memory slot name {:?}", name));
+        }
+        if self.name_taken(name) {
+            return fail(line, format!("duplicate name {}", name));
+        }
+
+        let obj = parse_literal(line, literal)?;
+        self.slots.insert(name.to_string(), self.mem.len());
+        self.mem.push(obj);
+        Ok(())
+    }
+
+    fn parse_byte(&mut self, line: usize, rest: &str) -> AsmResult<()> {
+        let byte = match rest.strip_prefix("0x") {
+            Some(hex) => u8::from_str_radix(hex, 16),
+            None => rest.parse::<u8>(),
+        };
+        match byte {
+            Err(_) => fail(line, format!("invalid byte {:?}", rest)),
+            Ok(b) => {
+                self.items.push((line, Item::Byte(b)));
+                self.offset += 1;
+                Ok(())
+            }
+        }
+    }
+
+    fn parse_instruction(&mut self, line: usize, mnemonic: &str, rest: &str)
+        -> AsmResult<()> {
+        let opcode = INSTRUCTION_SET.iter().position(|o| o.name == mnemonic);
+        let opcode = match opcode {
+            None => return fail(line, format!("unknown mnemonic {}", mnemonic)),
+            Some(opcode) => opcode,
+        };
+        let operand_offset = INSTRUCTION_SET[opcode].operand_offset;
+
+        let operand = match (operand_offset, rest.is_empty()) {
+            (0, true) => None,
+            (0, false) => return fail(
+                line, format!("{} takes no operand", mnemonic)),
+            (_, true) => return fail(
+                line, format!("{} expects an operand", mnemonic)),
+            (_, false) if is_ident(rest) && !is_keyword(rest) =>
+                Some(Operand::Name(rest.to_string())),
+            (_, false) => Some(Operand::Literal(parse_literal(line, rest)?)),
+        };
+
+        self.items.push((line, Item::Instruction {
+            opcode: opcode as u8,
+            operand,
+        }));
+        self.offset += 1 + operand_offset;
+        Ok(())
+    }
+
+    fn emit(mut self) -> AsmResult<Vec<u8>> {
+        let mut code = Vec::new();
+        for (line, item) in std::mem::take(&mut self.items) {
+            match item {
+                Item::Byte(b) => code.push(b),
+                Item::Instruction { opcode, operand } => {
+                    code.push(opcode);
+                    if let Some(operand) = operand {
+                        let mp = self.resolve(line, opcode, operand)?;
+                        code.extend_from_slice(&(mp as u32).to_be_bytes());
+                    }
+                }
+            }
+        }
+        if code.is_empty() {
+            return fail(1, String::from("empty instructions list"));
+        }
+
+        let debug_info = self.debug.take().map(|mut debug| {
+            debug.names = self.slots.iter()
+                .map(|(name, mp)| (*mp, name.clone()))
+                .collect();
+            debug.labels = self.labels.iter()
+                .map(|(name, offset)| (name.clone(), *offset))
+                .collect();
+            debug
+        });
+        let mut metadata = serde_json::Map::new();
+        metadata.insert(String::from("producer"), json!(PRODUCER));
+
+        let container = Container {
+            version: FORMAT_VERSION,
+            mem: self.mem,
+            instructions: code,
+            debug_info,
+            metadata,
+            pool: Pool::Json,
+        };
+        container.to_bytes().or_else(|msg| fail(1, msg.to_string()))
+    }
+
+    fn resolve(&mut self, line: usize, opcode: u8, operand: Operand)
+        -> AsmResult<usize> {
+        match operand {
+            Operand::Name(name) => {
+                if let Some(mp) = self.slots.get(&name) {
+                    return Ok(*mp);
+                }
+                let init = match self.labels.get(&name) {
+                    Some(offset) => Obj::Int(*offset as i64),
+                    None => Obj::Null,
+                };
+                self.slots.insert(name, self.mem.len());
+                self.mem.push(init);
+                Ok(self.mem.len() - 1)
+            },
+            Operand::Literal(obj) => {
+                if INSTRUCTION_SET[opcode as usize].name != "push" {
+                    return fail(line, String::from(
+                        "literal operands are only allowed for push"));
+                }
+                let key = obj.to_literal();
+                if let Some(mp) = self.consts.get(&key) {
+                    return Ok(*mp);
+                }
+                self.consts.insert(key, self.mem.len());
+                self.mem.push(obj);
+                Ok(self.mem.len() - 1)
+            }
+        }
+    }
+}
+
+fn parse_literal(line: usize, text: &str) -> AsmResult<Obj> {
+    Obj::from_literal(text)
+        .or_else(|_| fail(line, format!("invalid literal {:?}", text)))
+}
+
+// Comments start with `@` anywhere outside of a string literal.
+fn strip_comment(text: &str) -> &str {
+    let mut in_string = false;
+    let mut escaped = false;
+    for (i, c) in text.char_indices() {
+        match c {
+            _ if escaped => escaped = false,
+            '\\' if in_string => escaped = true,
+            '"' => in_string = !in_string,
+            '@' if !in_string => return &text[..i],
+            _ => (),
+        }
+    }
+    text
+}
+
+// 1-based column at which `text`, a slice of `line`, starts.
+fn column(line: &str, text: &str) -> usize {
+    let start = text.as_ptr() as usize - line.as_ptr() as usize;
+    line[..start].chars().count() + 1
+}
+
+fn split_label(text: &str) -> Option<(&str, &str)> {
+    let (word, _) = split_word(text);
+    let label = word.strip_suffix(':')?;
+    if is_ident(label) {
+        Some((label, &text[word.len()..]))
+    } else {
+        None
+    }
+}
+
+fn split_word(text: &str) -> (&str, &str) {
+    match text.find(char::is_whitespace) {
+        None => (text, ""),
+        Some(i) => (&text[..i], text[i..].trim()),
+    }
+}
+
+pub(crate) fn is_ident(text: &str) -> bool {
+    let mut chars = text.chars();
+    match chars.next() {
+        Some(c) if c.is_ascii_alphabetic() || c == '_' =>
+            chars.all(|c| c.is_ascii_alphanumeric() || c == '_'),
+        _ => false,
+    }
+}
+
+pub(crate) fn is_keyword(text: &str) -> bool {
+    ["null", "true", "false"].contains(&text)
+}
+
+#[cfg(test)]
+mod asm_tests {
+    use super::*;
+    use crate::disasm;
+    use crate::vm::{MemHost, Op, Outcome, VM};
+
+    fn run(bytecode: &[u8], input: &str) -> (Outcome, String) {
+        let mut vm = VM::new(bytecode).unwrap();
+        let host = MemHost::new(input);
+        let output = host.output();
+        vm.set_host(Box::new(host));
+        let outcome = vm.boot();
+        let output = output.borrow().clone();
+        (outcome, output)
+    }
+
+    #[test]
+    fn assembles_memory_and_code() {
+        let src = "
+            .mem greeting \"hi @ there\"   @ not a comment inside quotes
+            .mem name null
+                push greeting
+                out
+                ins
+                pop name
+                end
+        ";
+        let container = Container::read(&assemble(src).unwrap()).unwrap();
+        assert_eq!(FORMAT_VERSION, container.version);
+        assert_eq!(vec![Obj::Str(String::from("hi @ there")), Obj::Null],
+                   container.mem);
+        assert_eq!(vec![
+            Op::Push.op(), 0, 0, 0, 0,
+            Op::Out.op(),
+            Op::Ins.op(),
+            Op::Pop.op(), 0, 0, 0, 1,
+            Op::End.op(),
+        ], container.instructions);
+        assert_eq!(None, container.debug_info);
+        assert_eq!(Some(&json!(PRODUCER)), container.metadata.get("producer"));
+    }
+
+    #[test]
+    fn resolves_labels_literals_and_variables() {
+        let src = "
+            .mem n 3
+            loop:
+                push n
+                out
+                push n
+                push 1
+                sub
+                pop n
+                push n
+                bool
+                push loop
+                jmpt
+                push \"!\"
+                out
+                push 1
+                pop unused
+                end
+        ";
+        let bytecode = assemble(src).unwrap();
+        assert_eq!([
+            Obj::Int(3), Obj::Int(1), Obj::Int(0),
+            Obj::Str(String::from("!")), Obj::Null,
+        ], VM::new(&bytecode).unwrap().mem());
+        assert_eq!((Outcome::End, String::from("321!")), run(&bytecode, ""));
+    }
+
+    #[test]
+    fn reports_errors_with_line_numbers() {
+        assert_eq!(Err(AsmError {
+            line: 2,
+            msg: String::from("unknown mnemonic jmp"),
+        }), assemble("nl\njmp"));
+        assert_eq!(Err(AsmError {
+            line: 1,
+            msg: String::from("push expects an operand"),
+        }), assemble("push"));
+        assert_eq!(Err(AsmError {
+            line: 1,
+            msg: String::from("literal operands are only allowed for push"),
+        }), assemble("pop 42"));
+        assert_eq!(Err(AsmError {
+            line: 2,
+            msg: String::from("duplicate name x"),
+        }), assemble("x: nl\n.mem x 1"));
+        assert_eq!(Err(AsmError {
+            line: 1,
+            msg: String::from("empty instructions list"),
+        }), assemble(".mem x 1"));
+    }
+
+    #[test]
+    fn round_trips_through_disassembler() {
+        let src = "
+            .mem prompt \"Input your age: \"
+            start:
+                push prompt
+                out
+                ini
+                push start
+                jump
+                .byte 0xff
+        ";
+        let bytecode = assemble(src).unwrap();
+        let listing = disasm::disassemble(&bytecode).unwrap();
+        assert_eq!(Ok(bytecode), assemble(&listing));
+    }
+
+    #[test]
+    fn records_debug_info() {
+        let src = "
+            .mem n 2
+            loop: push n
+                push loop
+            .loc \"count.so\" 7
+                jump
+            .loc \"count.so\" 8 3
+                end
+        ";
+        let bytecode = assemble_with_debug_info(src, "count.rasm").unwrap();
+        let vm = VM::new(&bytecode).unwrap();
+        let debug = vm.debug_info().unwrap();
+        assert_eq!(Some("n"), debug.name(0));
+        assert_eq!(Some("loop"), debug.name(1));
+        assert_eq!(Some(&0), debug.labels.get("loop"));
+        assert_eq!("count.rasm:3:19", debug.position(0).unwrap().to_string());
+        assert_eq!("count.rasm:4:17", debug.position(5).unwrap().to_string());
+        assert_eq!("count.so:7:1", debug.position(10).unwrap().to_string());
+        assert_eq!(Some(11), debug.offset("count.so", 8));
+
+        assert!(VM::new(&assemble(src).unwrap()).unwrap().debug_info().is_none());
+        assert_eq!(Err(AsmError {
+            line: 1,
+            msg: String::from(r#"invalid location "\"count.so\"""#),
+        }), assemble(".loc \"count.so\""));
+    }
+
+    #[test]
+    fn round_trips_debug_info_through_disassembler() {
+        let src = "
+            .mem prompt \"Input your age: \"
+            start:
+                push prompt
+                out
+                push start
+                jump
+        ";
+        let bytecode = assemble_with_debug_info(src, "age.rasm").unwrap();
+        let listing = disasm::disassemble(&bytecode).unwrap();
+        let again = assemble_with_debug_info(&listing, "age.rasm.dis").unwrap();
+        let (vm, again) = (VM::new(&bytecode).unwrap(), VM::new(&again).unwrap());
+        assert_eq!(vm.instructions(), again.instructions());
+        assert_eq!(vm.mem(), again.mem());
+        let (a, b) = (vm.debug_info().unwrap(), again.debug_info().unwrap());
+        for loc in &a.locations {
+            assert_eq!(a.position(loc.offset), b.position(loc.offset));
+        }
+        assert_eq!(a.names, b.names);
+    }
+}
@@ -0,0 +1,289 @@
+use std::env;
+use std::fs::File;
+use std::io;
+use std::io::{BufWriter, Write};
+use std::path::Path;
+
+extern crate colored;
+use colored::*;
+
+extern crate argparse;
+use argparse::{ArgumentParser, Collect, Store, StoreTrue};
+
+use rick::{DebugInfo, Outcome, Problem, Tracer, VM};
+use rick::vm::Pool;
+use rick::vm::DEFAULT_MAX_CALL_DEPTH;
+
+pub enum Command {
+    Run { src: String, vm: VmArgs, trace: TraceArgs },
+    Disasm { src: String },
+    Asm { src: String, out: String, debug_info: bool },
+    Convert { src: String, out: String, pool: Pool },
+    Debug { src: String, vm: VmArgs },
+    Dap,
+}
+
+/// Parse command line arguments. The first argument picks a subcommand; when
+/// it isn't one, the whole command line is treated as a request to run an
+/// executable. On `Err` the process should exit with the given code.
+pub fn command() -> Result<Command, i32> {
+    let mut args: Vec<String> = env::args().collect();
+    let subcommand: fn(Vec<String>) -> Result<Command, i32> =
+        match args.get(1).map(String::as_str) {
+            Some("asm") => asm_args,
+            Some("disasm") => disasm_args,
+            Some("convert") => convert_args,
+            Some("debug") => debug_args,
+            Some("dap") => dap_args,
+            _ => return run_args(args),
+        };
+
+    // Let usage messages read `rick asm ...` rather than just `rick ...`.
+    let name = args.remove(1);
+    args[0] = format!("{} {}", args[0], name);
+    subcommand(args)
+}
+
+#[derive(Default)]
+pub struct TraceArgs {
+    enabled: bool,
+    file: String,
+    ips: String,
+    opcodes: String,
+    mem_writes: bool,
+}
+
+/// VmArgs are the options that shape how a program runs, shared by `rick`
+/// and `rick debug`.
+pub struct VmArgs {
+    max_call_depth: usize,
+    int_bools: bool,
+    allow_read: Vec<String>,
+    allow_write: Vec<String>,
+}
+
+impl Default for VmArgs {
+    fn default() -> Self {
+        Self {
+            max_call_depth: DEFAULT_MAX_CALL_DEPTH,
+            int_bools: false,
+            allow_read: Vec::new(),
+            allow_write: Vec::new(),
+        }
+    }
+}
+
+fn refer_vm_args<'a>(ap: &mut ArgumentParser<'a>, vm: &'a mut VmArgs) {
+    ap.refer(&mut vm.max_call_depth)
+        .add_option(&["--max-call-depth"], Store,
+                    "Maximum number of nested branches");
+    ap.refer(&mut vm.int_bools)
+        .add_option(&["--int-bools"], StoreTrue,
+                    "Run old bytecode that uses ints as booleans");
+    ap.refer(&mut vm.allow_read)
+        .add_option(&["--allow-read"], Collect,
+                    "Let the program read files inside DIR (repeatable)")
+        .metavar("DIR");
+    ap.refer(&mut vm.allow_write)
+        .add_option(&["--allow-write"], Collect,
+                    "Let the program write files inside DIR (repeatable)")
+        .metavar("DIR");
+}
+
+/// Apply the options to a freshly loaded VM.
+pub fn configure(vm: &mut VM, args: &VmArgs) -> Result<(), String> {
+    vm.set_max_call_depth(args.max_call_depth);
+    vm.set_int_bools(args.int_bools);
+    for dir in &args.allow_read {
+        vm.allow_read(Path::new(dir))
+            .map_err(|e| format!("cannot allow reading {:?}: {}", dir, e))?;
+    }
+    for dir in &args.allow_write {
+        vm.allow_write(Path::new(dir))
+            .map_err(|e| format!("cannot allow writing {:?}: {}", dir, e))?;
+    }
+    Ok(())
+}
+
+fn run_args(args: Vec<String>) -> Result<Command, i32> {
+    let mut src = String::from("");
+    let mut vm = VmArgs::default();
+    let mut trace = TraceArgs::default();
+    {
+        let mut ap = ArgumentParser::new();
+        ap.set_description("Execute SmallO bytecode. \
+                            Use `rick asm` and `rick disasm` to convert \
+                            between bytecode and assembly, and `rick debug` \
+                            to step through it.");
+        ap.refer(&mut src)
+            .add_argument("source", Store,
+                          "Path to SmallO assembly source code");
+        refer_vm_args(&mut ap, &mut vm);
+        ap.refer(&mut trace.enabled)
+            .add_option(&["--trace"], StoreTrue,
+                        "Log every executed instruction to stderr");
+        ap.refer(&mut trace.file)
+            .add_option(&["--trace-file"], Store,
+                        "Log executed instructions to a file instead");
+        ap.refer(&mut trace.ips)
+            .add_option(&["--trace-ip"], Store,
+                        "Only trace offsets in range START:END");
+        ap.refer(&mut trace.opcodes)
+            .add_option(&["--trace-op"], Store,
+                        "Only trace these comma-separated mnemonics");
+        ap.refer(&mut trace.mem_writes)
+            .add_option(&["--trace-mem"], StoreTrue,
+                        "Also trace memory writes made by pop");
+        ap.parse(args, &mut io::stdout(), &mut io::stderr())?;
+    }
+    Ok(Command::Run { src, vm, trace })
+}
+
+/// Build a tracer out of trace arguments, if tracing was asked for at all.
+/// Any of the `--trace-*` options turns tracing on.
+pub fn tracer(args: &TraceArgs) -> Result<Option<Tracer>, String> {
+    let enabled = args.enabled || args.mem_writes || !args.file.is_empty()
+        || !args.ips.is_empty() || !args.opcodes.is_empty();
+    if !enabled {
+        return Ok(None);
+    }
+
+    let out: Box<dyn Write> = if args.file.is_empty() {
+        Box::new(io::stderr())
+    } else {
+        let file = File::create(&args.file)
+            .map_err(|e| format!("failed to create trace file: {}", e))?;
+        Box::new(BufWriter::new(file))
+    };
+    let mut tracer = Tracer::new(out);
+
+    if !args.ips.is_empty() {
+        let bounds: Vec<Option<usize>> = args.ips.split(':')
+            .map(|b| b.trim().parse().ok())
+            .collect();
+        match bounds[..] {
+            [Some(start), Some(end)] if start <= end =>
+                tracer.set_ip_range(start..=end),
+            _ => return Err(format!("invalid trace range {:?}", args.ips)),
+        }
+    }
+    if !args.opcodes.is_empty() {
+        tracer.set_opcodes(args.opcodes.split(',')
+            .map(|o| o.trim().to_string())
+            .collect())?;
+    }
+    tracer.set_mem_writes(args.mem_writes);
+
+    Ok(Some(tracer))
+}
+
+fn disasm_args(args: Vec<String>) -> Result<Command, i32> {
+    let mut src = String::from("");
+    {
+        let mut ap = ArgumentParser::new();
+        ap.set_description("Disassemble SmallO bytecode");
+        ap.refer(&mut src)
+            .add_argument("source", Store, "Path to SmallO executable")
+            .required();
+        ap.parse(args, &mut io::stdout(), &mut io::stderr())?;
+    }
+    Ok(Command::Disasm { src })
+}
+
+fn convert_args(args: Vec<String>) -> Result<Command, i32> {
+    let mut src = String::from("");
+    let mut out = String::from("");
+    let mut pool = String::from("json");
+    {
+        let mut ap = ArgumentParser::new();
+        ap.set_description("Rewrite SmallO bytecode in the current format");
+        ap.refer(&mut src)
+            .add_argument("source", Store, "Path to SmallO executable")
+            .required();
+        ap.refer(&mut out)
+            .add_option(&["-o", "--output"], Store,
+                        "Path to the converted executable")
+            .required();
+        ap.refer(&mut pool)
+            .add_option(&["--pool"], Store,
+                        "Constant pool encoding: json (default) or binary");
+        ap.parse(args, &mut io::stdout(), &mut io::stderr())?;
+    }
+    let pool = match pool.as_str() {
+        "json" => Pool::Json,
+        "binary" => Pool::Binary,
+        _ => {
+            report_err(&format!("unknown constant pool encoding {:?}", pool));
+            return Err(2);
+        },
+    };
+    Ok(Command::Convert { src, out, pool })
+}
+
+fn debug_args(args: Vec<String>) -> Result<Command, i32> {
+    let mut src = String::from("");
+    let mut vm = VmArgs::default();
+    {
+        let mut ap = ArgumentParser::new();
+        ap.set_description("Step through SmallO bytecode interactively");
+        ap.refer(&mut src)
+            .add_argument("source", Store, "Path to SmallO executable")
+            .required();
+        refer_vm_args(&mut ap, &mut vm);
+        ap.parse(args, &mut io::stdout(), &mut io::stderr())?;
+    }
+    Ok(Command::Debug { src, vm })
+}
+
+fn dap_args(args: Vec<String>) -> Result<Command, i32> {
+    let mut ap = ArgumentParser::new();
+    ap.set_description("Serve the Debug Adapter Protocol over stdio");
+    ap.parse(args, &mut io::stdout(), &mut io::stderr())?;
+    Ok(Command::Dap)
+}
+
+fn asm_args(args: Vec<String>) -> Result<Command, i32> {
+    let mut src = String::from("");
+    let mut out = String::from("");
+    let mut debug_info = false;
+    {
+        let mut ap = ArgumentParser::new();
+        ap.set_description("Assemble SmallO assembly into bytecode");
+        ap.refer(&mut src)
+            .add_argument("source", Store, "Path to SmallO assembly")
+            .required();
+        ap.refer(&mut out)
+            .add_option(&["-o", "--output"], Store,
+                        "Path to the executable (defaults to SOURCE.rk)");
+        ap.refer(&mut debug_info)
+            .add_option(&["-g", "--debug-info"], StoreTrue,
+                        "Embed a source map for debuggers and error reports");
+        ap.parse(args, &mut io::stdout(), &mut io::stderr())?;
+    }
+    if out.is_empty() {
+        out = Path::new(&src).with_extension("rk").to_string_lossy().into();
+    }
+    Ok(Command::Asm { src, out, debug_info })
+}
+
+pub fn report_err(err: &str) {
+    println!("{}", format!("Error: {}", err).red());
+}
+
+pub fn report_problems(problems: &[Problem]) {
+    for problem in problems {
+        report_err(&problem.to_string());
+    }
+}
+
+pub fn report_outcome(outcome: &Outcome, debug_info: Option<&DebugInfo>) {
+    if let Outcome::Fault(err) = outcome {
+        let location = match debug_info.and_then(|d| d.position(err.ip)) {
+            Some(pos) => format!("{} (#{})", pos, err.ip),
+            None => format!("#{}", err.ip),
+        };
+        let panic_msg = format!("Rick panicked at {}!", location).yellow();
+        let error_msg = format!("Error: {}.", err).red();
+        println!("{}\n{}", panic_msg, error_msg);
+    }
+}
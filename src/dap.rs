@@ -0,0 +1,858 @@
+extern crate serde_json;
+use serde_json::{json, Value};
+
+use std::cell::RefCell;
+use std::collections::{BTreeMap, BTreeSet};
+use std::io;
+use std::io::{BufRead, Read, Write};
+use std::path::Path;
+use std::rc::Rc;
+use std::sync::mpsc::{self, TryRecvError};
+use std::thread;
+
+use crate::disasm::{self, Listing};
+use crate::vm::{MemHost, Outcome, VM};
+
+const THREAD_ID: i64 = 1;
+const STACK_REF: i64 = 1;
+const MEM_REF: i64 = 2;
+
+// Messages are read into memory whole, so a bogus length mustn't make us
+// allocate whatever it asks for.
+const MAX_MESSAGE_LEN: usize = 16 * 1024 * 1024;
+
+/// Serve the Debug Adapter Protocol over a pair of streams until the client
+/// disconnects or closes its end.
+///
+/// Requests are read on a thread of their own, so that a running program can
+/// be paused or abandoned: while it runs, the program gets a slice of
+/// instructions at a time, with pending requests handled in between.
+pub fn serve<R, W>(input: R, mut output: W) -> io::Result<()>
+    where R: BufRead + Send + 'static, W: Write {
+    let (requests, received) = mpsc::channel();
+    thread::spawn(move || {
+        let mut input = input;
+        loop {
+            let request = read_message(&mut input);
+            let last = match &request {
+                Ok(request) => request.is_none(),
+                Err(e) => !malformed(e),
+            };
+            if requests.send(request).is_err() || last {
+                break;
+            }
+        }
+    });
+
+    let mut session = Session::new();
+    while !session.finished() {
+        let request = if session.busy() {
+            match received.try_recv() {
+                Err(TryRecvError::Empty) => {
+                    for message in session.resume() {
+                        write_message(&mut output, &message)?;
+                    }
+                    continue;
+                },
+                Err(TryRecvError::Disconnected) => break,
+                Ok(request) => request,
+            }
+        } else {
+            match received.recv() {
+                Err(_) => break,
+                Ok(request) => request,
+            }
+        };
+        // A garbled message is reported and skipped; only a broken or closed
+        // stream ends the session.
+        let request = match request {
+            Ok(None) => break,
+            Ok(Some(request)) => request,
+            Err(e) if malformed(&e) => {
+                let event = session.complaint(&e.to_string());
+                write_message(&mut output, &event)?;
+                continue;
+            },
+            Err(e) => return Err(e),
+        };
+        for message in session.handle(&request) {
+            write_message(&mut output, &message)?;
+        }
+    }
+    Ok(())
+}
+
+fn read_message<R: BufRead>(input: &mut R) -> io::Result<Option<Value>> {
+    let mut length = None;
+    loop {
+        let mut header = String::new();
+        if input.read_line(&mut header)? == 0 {
+            return Ok(None);
+        }
+        let header = header.trim();
+        if header.is_empty() {
+            break;
+        }
+        // After a message without a length its body runs into the next
+        // header, so look for the header anywhere on the line.
+        if let Some((_, value)) = header.split_once("Content-Length:") {
+            length = value.trim().parse::<usize>().ok();
+        }
+    }
+
+    let length = length.ok_or_else(|| invalid("missing Content-Length header"))?;
+    if length > MAX_MESSAGE_LEN {
+        io::copy(&mut input.take(length as u64), &mut io::sink())?;
+        return Err(invalid("Content-Length too large"));
+    }
+    let mut body = vec![0; length];
+    input.read_exact(&mut body)?;
+    serde_json::from_slice(&body)
+        .map(Some)
+        .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+}
+
+fn invalid(msg: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, msg)
+}
+
+// Whether reading failed on a bad message rather than on the stream itself.
+fn malformed(e: &io::Error) -> bool {
+    e.kind() == io::ErrorKind::InvalidData
+}
+
+fn write_message<W: Write>(output: &mut W, message: &Value) -> io::Result<()> {
+    let body = message.to_string();
+    write!(output, "Content-Length: {}\r\n\r\n{}", body.len(), body)?;
+    output.flush()
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum Step {
+    Instruction,
+    Over,
+    Out,
+    Continue,
+}
+
+/// How many instructions `resume` runs before returning to check for requests.
+const SLICE: usize = 10_000;
+
+/// Session keeps the state of a single debugging session. Requests that run
+/// the program only set it going; `resume` then runs it a slice at a time
+/// until it has something to report, and a `pause` request stops it in
+/// between.
+///
+/// Programs built with debug info are shown in terms of their own source
+/// files. Otherwise the "source" shown to the client is the program's
+/// disassembly and breakpoints are set on its lines.
+pub struct Session {
+    seq: i64,
+    vm: Option<VM>,
+    name: String,
+    listing: Listing,
+    output: Rc<RefCell<String>>,
+    // Clients set breakpoints one source file at a time, so they are kept
+    // per source.
+    breakpoints: BTreeMap<String, BTreeSet<usize>>,
+    // How the program is being run, with the call depth it started at.
+    running: Option<(Step, usize)>,
+    stop_on_entry: bool,
+    fault_reported: bool,
+    finished: bool,
+}
+
+impl Default for Session {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Session {
+    pub fn new() -> Self {
+        Self {
+            seq: 0,
+            vm: None,
+            name: String::new(),
+            listing: disasm::listing(&[], &[], None),
+            output: Rc::new(RefCell::new(String::new())),
+            breakpoints: BTreeMap::new(),
+            running: None,
+            stop_on_entry: false,
+            fault_reported: false,
+            finished: false,
+        }
+    }
+
+    pub fn finished(&self) -> bool {
+        self.finished
+    }
+
+    /// Whether the program is running and waiting for `resume`.
+    pub fn busy(&self) -> bool {
+        self.running.is_some()
+    }
+
+    /// Handle a single request and return the response followed by any
+    /// events it caused.
+    pub fn handle(&mut self, request: &Value) -> Vec<Value> {
+        let command = request["command"].as_str().unwrap_or("").to_string();
+        let args = &request["arguments"];
+        let mut events = Vec::new();
+
+        let body = match command.as_str() {
+            "initialize" => Ok(json!({
+                "supportsConfigurationDoneRequest": true,
+            })),
+            "launch" => self.launch(args).inspect(|_| {
+                events.push(self.event("initialized", json!({})));
+            }),
+            "setBreakpoints" => Ok(self.set_breakpoints(args)),
+            "configurationDone" => {
+                if self.stop_on_entry {
+                    events.push(self.stopped("entry", None));
+                } else {
+                    self.start(Step::Continue);
+                }
+                Ok(json!({}))
+            },
+            "threads" => Ok(json!({
+                "threads": [{ "id": THREAD_ID, "name": "main" }],
+            })),
+            "stackTrace" => self.stack_trace(),
+            "scopes" => Ok(json!({
+                "scopes": [
+                    { "name": "Stack", "variablesReference": STACK_REF,
+                      "expensive": false },
+                    { "name": "Memory", "variablesReference": MEM_REF,
+                      "expensive": false },
+                ],
+            })),
+            "variables" => self.variables(args),
+            "source" => Ok(json!({ "content": self.listing.text })),
+            "continue" => {
+                self.start(Step::Continue);
+                Ok(json!({ "allThreadsContinued": true }))
+            },
+            "next" | "stepIn" | "stepOut" => {
+                self.start(match command.as_str() {
+                    "next" => Step::Over,
+                    "stepOut" => Step::Out,
+                    _ => Step::Instruction,
+                });
+                Ok(json!({}))
+            },
+            "pause" => {
+                if self.running.take().is_some() {
+                    events = self.stop("pause");
+                }
+                Ok(json!({}))
+            },
+            "disconnect" => {
+                self.running = None;
+                self.finished = true;
+                Ok(json!({}))
+            },
+            _ => Err(format!("unsupported request {}", command)),
+        };
+
+        let mut response = json!({
+            "type": "response",
+            "request_seq": request["seq"],
+            "command": command,
+            "success": body.is_ok(),
+        });
+        match body {
+            Ok(body) => response["body"] = body,
+            Err(msg) => response["message"] = json!(msg),
+        }
+        response["seq"] = json!(self.next_seq());
+
+        let mut messages = vec![response];
+        messages.extend(self.numbered(events));
+        messages
+    }
+
+    /// Run the program for another slice and return the events it caused:
+    /// output so far, and the reason it stopped once it does.
+    pub fn resume(&mut self) -> Vec<Value> {
+        let (step, depth) = match self.running {
+            None => return Vec::new(),
+            Some(running) => running,
+        };
+        let vm = match self.vm.as_mut() {
+            None => return Vec::new(),
+            Some(vm) => vm,
+        };
+
+        let mut reason = "step";
+        let mut ticks = 0;
+        while vm.running() {
+            if self.breakpoints.values().any(|b| b.contains(&vm.ip())) {
+                reason = "breakpoint";
+                break;
+            }
+            let done = match step {
+                Step::Instruction => true,
+                Step::Over => vm.calls().len() <= depth,
+                Step::Out => vm.calls().len() < depth,
+                Step::Continue => false,
+            };
+            if done {
+                break;
+            }
+            if ticks == SLICE {
+                let events = self.output_event().into_iter().collect();
+                return self.numbered(events);
+            }
+            vm.tick();
+            ticks += 1;
+        }
+
+        self.running = None;
+        let events = self.stop(reason);
+        self.numbered(events)
+    }
+
+    fn numbered(&mut self, events: Vec<Value>) -> Vec<Value> {
+        events.into_iter().map(|mut event| {
+            event["seq"] = json!(self.next_seq());
+            event
+        }).collect()
+    }
+
+    fn launch(&mut self, args: &Value) -> Result<Value, String> {
+        let program = match args["program"].as_str() {
+            None => return Err(String::from("launch needs a program path")),
+            Some(program) => program,
+        };
+        let mut vm = crate::load(program).map_err(|e| e.to_string())?;
+        if let Err(problems) = vm.verify() {
+            return Err(problems.iter()
+                .map(|p| p.to_string())
+                .collect::<Vec<_>>()
+                .join("\n"));
+        }
+
+        if let Some(depth) = args["maxCallDepth"].as_u64() {
+            vm.set_max_call_depth(depth as usize);
+        }
+        vm.set_int_bools(args["intBools"].as_bool().unwrap_or(false));
+        for dir in dirs(&args["allowRead"]) {
+            vm.allow_read(Path::new(dir)).map_err(|e| {
+                format!("cannot allow reading {:?}: {}", dir, e)
+            })?;
+        }
+        for dir in dirs(&args["allowWrite"]) {
+            vm.allow_write(Path::new(dir)).map_err(|e| {
+                format!("cannot allow writing {:?}: {}", dir, e)
+            })?;
+        }
+
+        let host = MemHost::new(args["input"].as_str().unwrap_or(""));
+        self.output = host.output();
+        vm.set_host(Box::new(host));
+
+        self.listing = disasm::listing(vm.mem(), vm.instructions(),
+                                       vm.debug_info());
+        self.name = format!("{} (disassembly)", program);
+        self.stop_on_entry = args["stopOnEntry"].as_bool().unwrap_or(false);
+        self.vm = Some(vm);
+        Ok(json!({}))
+    }
+
+    fn source(&self) -> Value {
+        json!({ "name": self.name, "sourceReference": 1 })
+    }
+
+    // Breakpoints in the disassembly are set on its lines, and breakpoints in
+    // any other source file are resolved through the debug info, if there is
+    // any.
+    fn set_breakpoints(&mut self, args: &Value) -> Value {
+        let debug = self.vm.as_ref().and_then(VM::debug_info);
+        let path = args["source"]["path"].as_str();
+        let disassembly = path.is_none() || path == Some(&self.name);
+        let key = path.unwrap_or(&self.name).to_string();
+        let mut offsets = BTreeSet::new();
+        let mut verified = Vec::new();
+        let lines = args["breakpoints"].as_array().cloned().unwrap_or_default();
+        for bp in lines {
+            let line = bp["line"].as_u64().unwrap_or(0) as usize;
+            let offset = match (debug, path) {
+                _ if disassembly => self.listing.offset(line),
+                (Some(debug), Some(path)) => debug.offset(path, line),
+                _ => None,
+            };
+            let message = if disassembly || debug.is_some() {
+                "no instruction on this line"
+            } else {
+                "program has no debug info"
+            };
+            match offset {
+                None => verified.push(json!({
+                    "verified": false,
+                    "line": line,
+                    "message": message,
+                })),
+                Some(offset) => {
+                    offsets.insert(offset);
+                    verified.push(json!({ "verified": true, "line": line }));
+                },
+            }
+        }
+        self.breakpoints.insert(key, offsets);
+        json!({ "breakpoints": verified })
+    }
+
+    fn stack_trace(&self) -> Result<Value, String> {
+        let vm = self.vm.as_ref().ok_or("no program launched")?;
+        let mut frames = vec![self.frame(0, self.current_ip(vm))];
+        for (i, ip) in vm.calls().iter().rev().enumerate() {
+            frames.push(self.frame(i as i64 + 1, *ip));
+        }
+        Ok(json!({ "stackFrames": frames, "totalFrames": frames.len() }))
+    }
+
+    fn frame(&self, id: i64, ip: usize) -> Value {
+        let debug = self.vm.as_ref().and_then(VM::debug_info);
+        let (source, line, column) = match debug.and_then(|d| d.position(ip)) {
+            Some(pos) => (source_file(pos.file), pos.line, pos.column),
+            None => (self.source(), self.listing.line(ip).unwrap_or(0), 1),
+        };
+        json!({
+            "id": id,
+            "name": format!("#{}", ip),
+            "source": source,
+            "line": line,
+            "column": column,
+            "instructionPointerReference": ip.to_string(),
+        })
+    }
+
+    fn current_ip(&self, vm: &VM) -> usize {
+        match vm.fault() {
+            Some(err) => err.ip,
+            None => vm.ip(),
+        }
+    }
+
+    fn variables(&self, args: &Value) -> Result<Value, String> {
+        let vm = self.vm.as_ref().ok_or("no program launched")?;
+        let variables: Vec<Value> = match args["variablesReference"].as_i64() {
+            Some(STACK_REF) => vm.stack().iter().enumerate().rev()
+                .map(|(i, obj)| variable(format!("[{}]", i), obj))
+                .collect(),
+            Some(MEM_REF) => vm.mem().iter().enumerate()
+                .map(|(i, obj)| {
+                    variable(disasm::slot_name(i, vm.debug_info()), obj)
+                })
+                .collect(),
+            _ => return Err(String::from("unknown variables reference")),
+        };
+        Ok(json!({ "variables": variables }))
+    }
+
+    // Set the program going. The first instruction always runs, so that
+    // stepping leaves a breakpoint the program is stopped at.
+    fn start(&mut self, step: Step) {
+        if let Some(vm) = self.vm.as_mut() {
+            let depth = vm.calls().len();
+            if vm.running() {
+                vm.tick();
+            }
+            self.running = Some((step, depth));
+        }
+    }
+
+    // Events to send once the program stops for `reason`.
+    fn stop(&mut self, reason: &str) -> Vec<Value> {
+        let mut events: Vec<Value> = self.output_event().into_iter().collect();
+        let outcome = self.vm.as_ref().and_then(VM::outcome);
+        match outcome {
+            None => events.push(self.stopped(reason, None)),
+            Some(Outcome::Fault(err)) if !self.fault_reported => {
+                self.fault_reported = true;
+                events.push(self.stopped("exception", Some(err.to_string())));
+            },
+            Some(outcome) => {
+                events.push(self.event("exited", json!({
+                    "exitCode": outcome.exit_code(),
+                })));
+                events.push(self.event("terminated", json!({})));
+            },
+        }
+        events
+    }
+
+    fn output_event(&self) -> Option<Value> {
+        let output = self.output.replace(String::new());
+        if output.is_empty() {
+            return None;
+        }
+        Some(self.event("output", json!({
+            "category": "stdout",
+            "output": output,
+        })))
+    }
+
+    // An event telling the client about a message that couldn't be read.
+    fn complaint(&mut self, problem: &str) -> Value {
+        let mut event = self.event("output", json!({
+            "category": "stderr",
+            "output": format!("ignoring malformed message: {}\n", problem),
+        }));
+        event["seq"] = json!(self.next_seq());
+        event
+    }
+
+    fn stopped(&self, reason: &str, text: Option<String>) -> Value {
+        let mut body = json!({
+            "reason": reason,
+            "threadId": THREAD_ID,
+            "allThreadsStopped": true,
+        });
+        if let Some(text) = text {
+            body["text"] = json!(text);
+        }
+        self.event("stopped", body)
+    }
+
+    fn event(&self, event: &str, body: Value) -> Value {
+        json!({ "type": "event", "event": event, "body": body })
+    }
+
+    fn next_seq(&mut self) -> i64 {
+        self.seq += 1;
+        self.seq
+    }
+}
+
+// Editors want absolute paths, while debug info holds whatever path the
+// program was built with.
+fn source_file(file: &str) -> Value {
+    let path = Path::new(file);
+    let name = path.file_name().map_or(file.into(), |n| n.to_string_lossy());
+    let path = path.canonicalize().unwrap_or_else(|_| path.to_path_buf());
+    json!({ "name": name, "path": path.to_string_lossy() })
+}
+
+fn dirs(arg: &Value) -> Vec<&str> {
+    arg.as_array()
+        .map(|dirs| dirs.iter().filter_map(Value::as_str).collect())
+        .unwrap_or_default()
+}
+
+fn variable(name: String, obj: &crate::vm::Obj) -> Value {
+    json!({
+        "name": name,
+        "value": obj.to_literal(),
+        "type": obj.type_name(),
+        "variablesReference": 0,
+    })
+}
+
+#[cfg(test)]
+mod dap_tests {
+    use super::*;
+    use std::io::Cursor;
+
+    // Handle the request and let the program run until it stops.
+    fn request(session: &mut Session, command: &str, arguments: Value)
+        -> Vec<Value> {
+        let mut messages = session.handle(&json!({
+            "seq": 1,
+            "type": "request",
+            "command": command,
+            "arguments": arguments,
+        }));
+        while session.busy() {
+            messages.extend(session.resume());
+        }
+        messages
+    }
+
+    fn events(messages: &[Value]) -> Vec<&str> {
+        messages.iter()
+            .filter(|m| m["type"] == "event")
+            .map(|m| m["event"].as_str().unwrap())
+            .collect()
+    }
+
+    fn launch(stop_on_entry: bool, input: &str) -> Session {
+        let mut session = Session::new();
+        request(&mut session, "initialize", json!({}));
+        let messages = request(&mut session, "launch", json!({
+            "program": "examples/bytecode/year_of_birth.rk",
+            "stopOnEntry": stop_on_entry,
+            "input": input,
+        }));
+        assert_eq!(true, messages[0]["success"]);
+        assert_eq!(vec!["initialized"], events(&messages));
+        session
+    }
+
+    #[test]
+    fn frames_messages() {
+        let mut input = Cursor::new(
+            b"Content-Length: 13\r\n\r\n{\"seq\": 1234}".to_vec());
+        let message = read_message(&mut input).unwrap().unwrap();
+        assert_eq!(json!({ "seq": 1234 }), message);
+        assert!(read_message(&mut input).unwrap().is_none());
+
+        let mut output = Vec::new();
+        write_message(&mut output, &message).unwrap();
+        assert_eq!(b"Content-Length: 12\r\n\r\n{\"seq\":1234}".to_vec(), output);
+
+        let mut input = Cursor::new(b"Content-Length: 99999999999\r\n\r\n".to_vec());
+        assert!(read_message(&mut input).is_err());
+    }
+
+    #[test]
+    fn skips_malformed_messages() {
+        let input = Cursor::new(
+            b"Content-Length: 5\r\n\r\n{bad}\
+              Content-Type: json\r\n\r\n{\"seq\": 1}\
+              Content-Length: 32\r\n\r\n\
+              {\"seq\": 2, \"command\": \"threads\"}\
+              Content-Length: 99999999999\r\n\r\n{}".to_vec());
+        let mut output = Vec::new();
+        serve(input, &mut output).unwrap();
+
+        let output = String::from_utf8(output).unwrap();
+        assert_eq!(3, output.matches("ignoring malformed message").count());
+        assert!(output.contains("\"command\":\"threads\",\"request_seq\":2"));
+    }
+
+    // Assemble `src` with debug info into a temporary executable and launch
+    // it.
+    fn launch_asm(name: &str, src: &str, mut args: Value) -> Session {
+        let bytecode = crate::asm::assemble_with_debug_info(src, "test.rasm")
+            .unwrap();
+        let program = std::env::temp_dir()
+            .join(format!("rick-dap-{}-{}.rk", name, std::process::id()));
+        std::fs::write(&program, bytecode).unwrap();
+
+        args["program"] = json!(program.to_str().unwrap());
+        let mut session = Session::new();
+        let messages = request(&mut session, "launch", args);
+        std::fs::remove_file(&program).unwrap();
+        assert_eq!(true, messages[0]["success"]);
+        session
+    }
+
+    #[test]
+    fn pauses_running_programs() {
+        let mut session = launch_asm("pause", "
+            loop:
+                push loop
+                jump
+        ", json!({}));
+        let messages = session.handle(&json!({
+            "seq": 1, "type": "request", "command": "configurationDone",
+        }));
+        assert_eq!(Vec::<&str>::new(), events(&messages));
+        assert!(session.busy());
+        assert!(session.resume().is_empty());
+        assert!(session.busy());
+
+        let messages = request(&mut session, "pause", json!({}));
+        assert!(!session.busy());
+        assert_eq!(vec!["stopped"], events(&messages));
+        assert_eq!("pause", messages[1]["body"]["reason"]);
+    }
+
+    #[test]
+    fn keeps_breakpoints_per_source() {
+        let mut session = launch_asm("sources", "
+            .loc \"a.so\" 1
+                nl
+            .loc \"a.so\" 2
+                nl
+            .loc \"b.so\" 1
+                nl
+                end
+        ", json!({}));
+        for (path, line) in &[("/work/a.so", 2), ("/work/b.so", 1)] {
+            request(&mut session, "setBreakpoints", json!({
+                "source": { "path": path },
+                "breakpoints": [{ "line": line }],
+            }));
+        }
+
+        let messages = request(&mut session, "configurationDone", json!({}));
+        assert_eq!(vec!["output", "stopped"], events(&messages));
+        assert_eq!("breakpoint", messages[2]["body"]["reason"]);
+        let messages = request(&mut session, "continue", json!({}));
+        assert_eq!(vec!["output", "stopped"], events(&messages));
+        assert_eq!("breakpoint", messages[2]["body"]["reason"]);
+    }
+
+    #[test]
+    fn runs_to_completion() {
+        let mut session = launch(false, "30\n");
+        let messages = request(&mut session, "configurationDone", json!({}));
+        assert_eq!(vec!["output", "exited", "terminated"], events(&messages));
+        assert_eq!("Input your age: You were born in 1990\n",
+                   messages[1]["body"]["output"]);
+        assert_eq!(0, messages[2]["body"]["exitCode"]);
+    }
+
+    #[test]
+    fn stops_at_breakpoints_and_shows_state() {
+        let mut session = launch(false, "30\n");
+        // Line 13 of the listing holds the `push m2` instruction at #12.
+        let messages = request(&mut session, "setBreakpoints", json!({
+            "source": { "sourceReference": 1 },
+            "breakpoints": [{ "line": 13 }, { "line": 2 }],
+        }));
+        assert_eq!(json!([
+            { "verified": true, "line": 13 },
+            { "verified": false, "line": 2,
+              "message": "no instruction on this line" },
+        ]), messages[0]["body"]["breakpoints"]);
+
+        let messages = request(&mut session, "configurationDone", json!({}));
+        assert_eq!(vec!["output", "stopped"], events(&messages));
+        assert_eq!("breakpoint", messages[2]["body"]["reason"]);
+
+        let messages = request(&mut session, "stackTrace", json!({}));
+        let frame = &messages[0]["body"]["stackFrames"][0];
+        assert_eq!(13, frame["line"]);
+        assert_eq!("12", frame["instructionPointerReference"]);
+
+        let messages = request(&mut session, "variables", json!({
+            "variablesReference": MEM_REF,
+        }));
+        assert_eq!(json!({
+            "name": "m1", "value": "30", "type": "int",
+            "variablesReference": 0,
+        }), messages[0]["body"]["variables"][1]);
+
+        let messages = request(&mut session, "next", json!({}));
+        assert_eq!(vec!["stopped"], events(&messages));
+        let messages = request(&mut session, "variables", json!({
+            "variablesReference": STACK_REF,
+        }));
+        assert_eq!("2020", messages[0]["body"]["variables"][0]["value"]);
+    }
+
+    #[test]
+    fn needs_debug_info_for_source_files() {
+        let mut session = launch(false, "30\n");
+        let messages = request(&mut session, "setBreakpoints", json!({
+            "source": { "path": "/work/year_of_birth.so" },
+            "breakpoints": [{ "line": 13 }],
+        }));
+        assert_eq!(json!([
+            { "verified": false, "line": 13,
+              "message": "program has no debug info" },
+        ]), messages[0]["body"]["breakpoints"]);
+
+        let messages = request(&mut session, "configurationDone", json!({}));
+        assert_eq!(vec!["output", "exited", "terminated"], events(&messages));
+    }
+
+    #[test]
+    fn reports_faults_as_exceptions() {
+        let mut session = launch(true, "");
+        let messages = request(&mut session, "configurationDone", json!({}));
+        assert_eq!("entry", messages[1]["body"]["reason"]);
+
+        request(&mut session, "stepIn", json!({}));
+        request(&mut session, "stepIn", json!({}));
+        let messages = request(&mut session, "stepIn", json!({}));
+        assert_eq!(vec!["stopped"], events(&messages));
+        assert_eq!("exception", messages[1]["body"]["reason"]);
+        assert_eq!("[ini] unexpected end of input", messages[1]["body"]["text"]);
+
+        let messages = request(&mut session, "stackTrace", json!({}));
+        assert_eq!("6", messages[0]["body"]["stackFrames"][0]
+                   ["instructionPointerReference"]);
+
+        let messages = request(&mut session, "continue", json!({}));
+        assert_eq!(vec!["exited", "terminated"], events(&messages));
+        assert_eq!(1, messages[1]["body"]["exitCode"]);
+    }
+
+    #[test]
+    fn maps_debug_info_to_source_files() {
+        let src = "
+            .mem n 2
+            .loc \"countdown.so\" 1
+                push n
+                pop seen
+            .loc \"countdown.so\" 2 5
+                push n
+                out
+                end
+        ";
+        let bytecode = crate::asm::assemble_with_debug_info(src, "countdown.rasm")
+            .unwrap();
+        let program = std::env::temp_dir()
+            .join(format!("rick-dap-{}.rk", std::process::id()));
+        std::fs::write(&program, bytecode).unwrap();
+
+        let mut session = Session::new();
+        let messages = request(&mut session, "launch", json!({
+            "program": program.to_str().unwrap(),
+        }));
+        std::fs::remove_file(&program).unwrap();
+        assert_eq!(true, messages[0]["success"]);
+
+        let messages = request(&mut session, "setBreakpoints", json!({
+            "source": { "path": "/work/countdown.so" },
+            "breakpoints": [{ "line": 2 }, { "line": 3 }],
+        }));
+        assert_eq!(json!([true, false]), json!(messages[0]["body"]["breakpoints"]
+            .as_array().unwrap().iter()
+            .map(|bp| bp["verified"].clone())
+            .collect::<Vec<_>>()));
+
+        let messages = request(&mut session, "configurationDone", json!({}));
+        assert_eq!("breakpoint", messages[1]["body"]["reason"]);
+        let messages = request(&mut session, "stackTrace", json!({}));
+        let frame = &messages[0]["body"]["stackFrames"][0];
+        assert_eq!("countdown.so", frame["source"]["name"]);
+        assert_eq!(2, frame["line"]);
+        assert_eq!(5, frame["column"]);
+
+        let messages = request(&mut session, "variables", json!({
+            "variablesReference": MEM_REF,
+        }));
+        let names: Vec<&str> = messages[0]["body"]["variables"]
+            .as_array().unwrap().iter()
+            .map(|v| v["name"].as_str().unwrap())
+            .collect();
+        assert_eq!(["n", "seen"], names[..]);
+    }
+
+    #[test]
+    fn applies_launch_options() {
+        let src = "
+                push 1
+                push done
+                jmpt
+            done:
+                end
+        ";
+        let mut session = launch_asm("strict", src, json!({}));
+        let messages = request(&mut session, "configurationDone", json!({}));
+        assert_eq!("exception", messages[1]["body"]["reason"]);
+
+        let mut session = launch_asm("compat", src, json!({
+            "intBools": true,
+            "maxCallDepth": 8,
+        }));
+        let messages = request(&mut session, "configurationDone", json!({}));
+        assert_eq!(vec!["exited", "terminated"], events(&messages));
+    }
+
+    #[test]
+    fn rejects_unknown_requests() {
+        let mut session = Session::new();
+        let messages = request(&mut session, "evaluate", json!({}));
+        assert_eq!(false, messages[0]["success"]);
+        request(&mut session, "disconnect", json!({}));
+        assert!(session.finished());
+    }
+}
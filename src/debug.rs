@@ -0,0 +1,394 @@
+use std::collections::BTreeSet;
+
+use crate::disasm;
+use crate::vm::{Obj, Outcome, VM};
+
+const HELP: &str = "\
+break LOCATION    (b)  set a breakpoint at an instruction offset, a label
+                       or FILE:LINE (labels and lines need debug info)
+delete LOCATION   (d)  remove a breakpoint
+breaks                 list breakpoints
+step [N]          (s)  execute N instructions (1 by default)
+continue          (c)  run until a breakpoint or the end of the program
+where             (w)  show the current instruction
+stack             (st) show the stack, bottom to top
+calls                  show return locations of pending branches
+mem [SLOT]        (m)  show all memory slots or just one, by number or name
+set SLOT LITERAL       overwrite a memory slot with a JSON literal
+push LITERAL           push a JSON literal onto the stack
+pop                    pop the top of the stack and show it
+quit              (q)  leave the debugger
+An empty line repeats the last command.";
+
+/// Debugger drives a VM one `tick` at a time on behalf of a user typing
+/// commands. It knows nothing about terminals: feed it command lines and show
+/// whatever it replies with.
+pub struct Debugger {
+    vm: VM,
+    starts: BTreeSet<usize>,
+    breakpoints: BTreeSet<usize>,
+    last: String,
+}
+
+impl Debugger {
+    pub fn new(vm: VM) -> Self {
+        let mut starts = BTreeSet::new();
+        let mut ip = 0;
+        while ip < vm.instructions().len() {
+            starts.insert(ip);
+            ip = disasm::decode(vm.mem(), vm.instructions(), ip, None).next;
+        }
+
+        Self {
+            vm,
+            starts,
+            breakpoints: BTreeSet::new(),
+            last: String::new(),
+        }
+    }
+
+    pub fn vm(&self) -> &VM {
+        &self.vm
+    }
+
+    /// Execute a single command line and return the reply to show. Returns
+    /// `None` once the user asked to quit.
+    pub fn command(&mut self, line: &str) -> Option<String> {
+        let line = match line.trim() {
+            "" => self.last.clone(),
+            line => line.to_string(),
+        };
+        self.last = line.clone();
+
+        let (cmd, arg) = match line.find(char::is_whitespace) {
+            None => (line.as_str(), ""),
+            Some(i) => (&line[..i], line[i..].trim()),
+        };
+
+        let reply = match cmd {
+            "" => String::new(),
+            "q" | "quit" => return None,
+            "h" | "help" => String::from(HELP),
+            "b" | "break" => self.set_breakpoint(arg),
+            "d" | "delete" => self.delete_breakpoint(arg),
+            "breaks" => self.list_breakpoints(),
+            "s" | "step" => self.step(arg),
+            "c" | "continue" => self.resume(),
+            "w" | "where" => self.location(),
+            "st" | "stack" => self.show_stack(),
+            "calls" => self.show_calls(),
+            "m" | "mem" => self.show_mem(arg),
+            "set" => self.set_mem(arg),
+            "push" => self.push(arg),
+            "pop" => self.pop(),
+            _ => format!("unknown command {:?}, try `help`", cmd),
+        };
+        Some(reply)
+    }
+
+    fn set_breakpoint(&mut self, arg: &str) -> String {
+        match self.resolve(arg) {
+            Err(err) => err,
+            Ok(offset) => {
+                self.breakpoints.insert(offset);
+                format!("breakpoint at #{}", offset)
+            }
+        }
+    }
+
+    fn delete_breakpoint(&mut self, arg: &str) -> String {
+        match self.resolve(arg) {
+            Err(err) => err,
+            Ok(offset) if self.breakpoints.remove(&offset) =>
+                format!("deleted breakpoint at #{}", offset),
+            Ok(offset) => format!("no breakpoint at #{}", offset),
+        }
+    }
+
+    fn resolve(&self, arg: &str) -> Result<usize, String> {
+        let offset = match arg.trim_start_matches('#').parse::<usize>() {
+            Ok(offset) => offset,
+            Err(_) if arg.is_empty() =>
+                return Err(String::from("expected an instruction offset")),
+            Err(_) => self.resolve_source(arg)?,
+        };
+        if !self.starts.contains(&offset) {
+            return Err(format!("#{} is not the start of an instruction",
+                               offset));
+        }
+        Ok(offset)
+    }
+
+    // Labels and FILE:LINE locations only mean something with debug info.
+    fn resolve_source(&self, arg: &str) -> Result<usize, String> {
+        let debug = match self.vm.debug_info() {
+            None => return Err(format!("no debug info to resolve {}", arg)),
+            Some(debug) => debug,
+        };
+        if let Some(offset) = debug.labels.get(arg) {
+            return Ok(*offset);
+        }
+        let line = arg.rsplit_once(':')
+            .and_then(|(file, line)| Some((file, line.parse().ok()?)));
+        match line {
+            None => Err(format!("no label {}", arg)),
+            Some((file, line)) => debug.offset(file, line)
+                .ok_or_else(|| format!("no code at {}", arg)),
+        }
+    }
+
+    fn list_breakpoints(&self) -> String {
+        if self.breakpoints.is_empty() {
+            return String::from("no breakpoints");
+        }
+        self.breakpoints.iter()
+            .map(|offset| format!("#{}", offset))
+            .collect::<Vec<_>>()
+            .join("\n")
+    }
+
+    fn step(&mut self, arg: &str) -> String {
+        let n = match arg {
+            "" => 1,
+            arg => match arg.parse::<usize>() {
+                Err(_) => return format!("invalid step count {:?}", arg),
+                Ok(n) => n,
+            },
+        };
+
+        for _ in 0..n {
+            if !self.vm.running() {
+                break;
+            }
+            self.vm.tick();
+        }
+        self.location()
+    }
+
+    fn resume(&mut self) -> String {
+        if self.vm.running() {
+            self.vm.tick();
+        }
+        while self.vm.running() && !self.breakpoints.contains(&self.vm.ip()) {
+            self.vm.tick();
+        }
+        self.location()
+    }
+
+    fn location(&self) -> String {
+        match self.vm.outcome() {
+            Some(Outcome::End) => String::from("program ended"),
+            Some(Outcome::Exit(code)) =>
+                format!("program exited with code {}", code),
+            Some(Outcome::Fault(err)) =>
+                format!("Rick panicked at #{}: {}", err.ip, err),
+            None => self.instruction(self.vm.ip()),
+        }
+    }
+
+    fn instruction(&self, ip: usize) -> String {
+        if ip >= self.vm.instructions().len() {
+            return format!("#{} end of instructions", ip);
+        }
+
+        let debug = self.vm.debug_info();
+        let instruction = disasm::decode(self.vm.mem(), self.vm.instructions(),
+                                         ip, debug);
+        let mark = if self.breakpoints.contains(&ip) { "*" } else { "" };
+        let mut text = format!("#{}{} {}", ip, mark, instruction.text);
+        if !instruction.note.is_empty() {
+            text.push_str(&format!("  @ {}", instruction.note));
+        }
+        if let Some(pos) = debug.and_then(|d| d.position(ip)) {
+            text.push_str(&format!("  ({})", pos));
+        }
+        text
+    }
+
+    fn show_stack(&self) -> String {
+        if self.vm.stack().is_empty() {
+            return String::from("stack is empty");
+        }
+        self.vm.stack().iter()
+            .enumerate()
+            .map(|(i, obj)| format!("[{}] {}", i, obj.to_literal()))
+            .collect::<Vec<_>>()
+            .join("\n")
+    }
+
+    fn show_calls(&self) -> String {
+        if self.vm.calls().is_empty() {
+            return String::from("call stack is empty");
+        }
+        self.vm.calls().iter()
+            .map(|ip| format!("#{}", ip))
+            .collect::<Vec<_>>()
+            .join("\n")
+    }
+
+    fn show_mem(&self, arg: &str) -> String {
+        let mem = self.vm.mem();
+        let slot = |mp: usize| {
+            let name = match self.vm.debug_info().and_then(|d| d.name(mp)) {
+                Some(name) => format!("m{} {}", mp, name),
+                None => format!("m{}", mp),
+            };
+            format!("{} = {}  ({})", name, mem[mp].to_literal(),
+                    mem[mp].type_name())
+        };
+        if arg.is_empty() {
+            return (0..mem.len()).map(slot).collect::<Vec<_>>().join("\n");
+        }
+        match self.parse_slot(arg) {
+            Some(mp) if mp < mem.len() => slot(mp),
+            _ => format!("no memory slot {}", arg),
+        }
+    }
+
+    fn set_mem(&mut self, arg: &str) -> String {
+        let (slot, literal) = match arg.find(char::is_whitespace) {
+            None => return String::from("expected a memory slot and a literal"),
+            Some(i) => (&arg[..i], arg[i..].trim()),
+        };
+        let mp = match self.parse_slot(slot) {
+            None => return format!("no memory slot {}", slot),
+            Some(mp) => mp,
+        };
+        let obj = match Obj::from_literal(literal) {
+            Err(err) => return err.to_string(),
+            Ok(obj) => obj,
+        };
+
+        match self.vm.set_mem(mp, obj) {
+            Err(err) => err.to_string(),
+            Ok(_) => self.show_mem(slot),
+        }
+    }
+
+    fn push(&mut self, arg: &str) -> String {
+        match Obj::from_literal(arg) {
+            Err(err) => err.to_string(),
+            Ok(obj) => {
+                let reply = format!("pushed {}", obj.to_literal());
+                self.vm.push_value(obj);
+                reply
+            }
+        }
+    }
+
+    fn pop(&mut self) -> String {
+        match self.vm.pop_value() {
+            None => String::from("stack is empty"),
+            Some(obj) => format!("popped {}", obj.to_literal()),
+        }
+    }
+
+    fn parse_slot(&self, text: &str) -> Option<usize> {
+        let by_name = self.vm.debug_info().and_then(|d| d.slot(text));
+        by_name.or_else(|| text.trim_start_matches('m').parse().ok())
+    }
+}
+
+#[cfg(test)]
+mod debug_tests {
+    use super::*;
+    use crate::asm;
+    use crate::vm::MemHost;
+
+    fn debugger(src: &str) -> Debugger {
+        let mut vm = VM::new(&asm::assemble(src).unwrap()).unwrap();
+        vm.set_host(Box::new(MemHost::new("")));
+        Debugger::new(vm)
+    }
+
+    fn reply(dbg: &mut Debugger, line: &str) -> String {
+        dbg.command(line).unwrap()
+    }
+
+    const COUNTDOWN: &str = "
+        .mem n 2
+        loop:
+            push n
+            push 1
+            sub
+            pop n
+            push n
+            bool
+            push loop
+            jmpt
+            end
+    ";
+
+    #[test]
+    fn steps_and_shows_location() {
+        let mut dbg = debugger(COUNTDOWN);
+        assert_eq!("#0 push m0  @ 2", reply(&mut dbg, "where"));
+        assert_eq!("#5 push m1  @ 1", reply(&mut dbg, "step"));
+        assert_eq!("#5 push m1  @ 1", reply(&mut dbg, "w"));
+        assert_eq!("#11 pop m0  @ 2", reply(&mut dbg, "s 2"));
+        assert_eq!("[0] 1", reply(&mut dbg, "stack"));
+    }
+
+    #[test]
+    fn continues_to_breakpoints() {
+        let mut dbg = debugger(COUNTDOWN);
+        assert_eq!("breakpoint at #16", reply(&mut dbg, "break 16"));
+        assert_eq!("#16* push m0  @ 1", reply(&mut dbg, "continue"));
+        assert_eq!("#16* push m0  @ 0", reply(&mut dbg, "c"));
+        assert_eq!("#16", reply(&mut dbg, "breaks"));
+        assert_eq!("deleted breakpoint at #16", reply(&mut dbg, "d 16"));
+        assert_eq!("program ended", reply(&mut dbg, "c"));
+        assert_eq!("program ended", reply(&mut dbg, "step"));
+    }
+
+    #[test]
+    fn rejects_bad_breakpoints() {
+        let mut dbg = debugger(COUNTDOWN);
+        assert_eq!("#3 is not the start of an instruction",
+                   reply(&mut dbg, "b 3"));
+        assert_eq!("no debug info to resolve loop", reply(&mut dbg, "b loop"));
+        assert_eq!("no breakpoint at #0", reply(&mut dbg, "d 0"));
+    }
+
+    #[test]
+    fn edits_memory_and_stack() {
+        let mut dbg = debugger(COUNTDOWN);
+        assert_eq!("m0 = 2  (int)\nm1 = 1  (int)\nm2 = 0  (int)",
+                   reply(&mut dbg, "mem"));
+        assert_eq!("m0 = \"hi\"  (str)", reply(&mut dbg, "set m0 \"hi\""));
+        assert_eq!("no memory slot m7", reply(&mut dbg, "m m7"));
+        assert_eq!("pushed 42", reply(&mut dbg, "push 42"));
+        assert_eq!("popped 42", reply(&mut dbg, "pop"));
+        assert_eq!("stack is empty", reply(&mut dbg, "pop"));
+    }
+
+    #[test]
+    fn uses_debug_info() {
+        let bytecode = asm::assemble_with_debug_info(COUNTDOWN, "countdown.rasm")
+            .unwrap();
+        let mut dbg = Debugger::new(VM::new(&bytecode).unwrap());
+        assert_eq!("#0 push n  @ 2  (countdown.rasm:4:13)",
+                   reply(&mut dbg, "where"));
+        assert_eq!("breakpoint at #0", reply(&mut dbg, "b loop"));
+        assert_eq!("breakpoint at #16", reply(&mut dbg, "b countdown.rasm:8"));
+        assert_eq!("no label nowhere", reply(&mut dbg, "b nowhere"));
+        assert_eq!("no code at countdown.rasm:2", reply(&mut dbg, "b countdown.rasm:2"));
+        assert_eq!("m0 n = 2  (int)", reply(&mut dbg, "m n"));
+        assert_eq!("m0 n = 5  (int)", reply(&mut dbg, "set n 5"));
+    }
+
+    #[test]
+    fn reports_faults() {
+        let mut dbg = debugger("add");
+        assert_eq!("Rick panicked at #0: [add] not enough values on the stack",
+                   reply(&mut dbg, "c"));
+    }
+
+    #[test]
+    fn repeats_last_command_and_quits() {
+        let mut dbg = debugger(COUNTDOWN);
+        reply(&mut dbg, "step");
+        assert_eq!("#10 sub", reply(&mut dbg, ""));
+        assert_eq!(None, dbg.command("quit"));
+    }
+}
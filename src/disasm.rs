@@ -0,0 +1,245 @@
+use crate::asm;
+use crate::vm::{Container, DebugInfo, LoadError, Obj};
+use crate::vm::op::INSTRUCTION_SET;
+
+/// Turn a compiled executable back into SmallO assembly.
+///
+/// Memory slots come out as `.mem` declarations named after their index and
+/// instructions refer to them by those names, so the listing can be fed back
+/// into the assembler. Offsets and referenced constants go into comments.
+/// With debug info, slots keep their variable names and source positions
+/// come out as `.loc` directives.
+pub fn disassemble(bytecode: &[u8]) -> Result<String, LoadError> {
+    let container = Container::read(bytecode)?;
+    Ok(listing(&container.mem, &container.instructions,
+               container.debug_info.as_ref()).text)
+}
+
+/// Listing is a disassembled program along with the instruction offset that
+/// each of its lines holds, if any.
+pub(crate) struct Listing {
+    pub text: String,
+    pub offsets: Vec<Option<usize>>,
+}
+
+impl Listing {
+    /// Find the 1-based line that holds the instruction at `offset`.
+    pub fn line(&self, offset: usize) -> Option<usize> {
+        self.offsets.iter().position(|o| *o == Some(offset)).map(|i| i + 1)
+    }
+
+    /// Find the instruction offset on a 1-based line.
+    pub fn offset(&self, line: usize) -> Option<usize> {
+        *self.offsets.get(line.checked_sub(1)?)?
+    }
+}
+
+pub(crate) fn listing(mem: &[Obj], instructions: &[u8],
+                      debug: Option<&DebugInfo>) -> Listing {
+    let mut text = String::from("@ memory\n");
+    let mut offsets = vec![None];
+    for (i, obj) in mem.iter().enumerate() {
+        let decl = format!(".mem {} {}", slot_name(i, debug), obj.to_literal());
+        text.push_str(&format!("{:<32} @ {} {}\n", decl, i, obj.type_name()));
+        offsets.push(None);
+    }
+
+    text.push_str("\n@ code\n");
+    offsets.extend(&[None, None]);
+
+    let mut ip = 0;
+    let mut pos = None;
+    while ip < instructions.len() {
+        let here = debug.and_then(|d| d.position(ip));
+        if let Some(p) = here.as_ref().filter(|p| Some(*p) != pos.as_ref()) {
+            text.push_str(&format!("    .loc {} {} {}\n",
+                                   serde_json::json!(p.file), p.line, p.column));
+            offsets.push(None);
+        }
+        pos = here;
+
+        let instruction = decode(mem, instructions, ip, debug);
+        text.push_str(&line(&instruction.text, ip, &instruction.note));
+        text.push('\n');
+        offsets.push(Some(ip));
+        ip = instruction.next;
+    }
+
+    Listing { text, offsets }
+}
+
+/// Instruction is a single line of a listing. Anything that can't be decoded
+/// as a whole instruction comes out as a `.byte` line.
+pub(crate) struct Instruction {
+    pub text: String,
+    pub note: String,
+    pub next: usize,
+}
+
+/// Decode the instruction at `ip`, which must be within `instructions`.
+pub(crate) fn decode(mem: &[Obj], instructions: &[u8], ip: usize,
+                     debug: Option<&DebugInfo>) -> Instruction {
+    let opcode = instructions[ip];
+    let byte = |note: String| Instruction {
+        text: format!(".byte 0x{:02x}", opcode),
+        note,
+        next: ip + 1,
+    };
+
+    let instruction = match INSTRUCTION_SET.get(opcode as usize) {
+        None => return byte(String::from("unknown opcode")),
+        Some(instruction) => instruction,
+    };
+
+    let n = instruction.operand_offset;
+    if n == 0 {
+        return Instruction {
+            text: instruction.name.to_string(),
+            note: String::new(),
+            next: ip + 1,
+        };
+    }
+
+    let operand = &instructions[ip + 1..];
+    if operand.len() < n {
+        return byte(format!("truncated {}", instruction.name));
+    }
+
+    let mut bytes = [0; 4];
+    bytes.copy_from_slice(&operand[..n]);
+    let mp = u32::from_be_bytes(bytes) as usize;
+    Instruction {
+        text: format!("{} {}", instruction.name, slot_name(mp, debug)),
+        note: match mem.get(mp) {
+            None => String::from("out of bounds"),
+            Some(obj) => obj.to_literal(),
+        },
+        next: ip + 1 + n,
+    }
+}
+
+fn line(text: &str, offset: usize, note: &str) -> String {
+    let code = format!("    {:<28} @ #{}", text, offset);
+    if note.is_empty() {
+        code
+    } else {
+        format!("{} {}", code, note)
+    }
+}
+
+/// Name a memory slot after its variable if debug info knows a name that
+/// the assembler would read back as the same slot, or after its index.
+pub(crate) fn slot_name(mp: usize, debug: Option<&DebugInfo>) -> String {
+    let name = debug.and_then(|d| d.name(mp))
+        .filter(|name| debug.and_then(|d| d.slot(name)) == Some(mp))
+        .filter(|name| is_plain_name(name));
+    match name {
+        Some(name) => name.to_string(),
+        None => format!("m{}", mp),
+    }
+}
+
+// Names that look like keywords or generated slot names would be read back
+// as something else.
+fn is_plain_name(name: &str) -> bool {
+    let generated = name.strip_prefix('m')
+        .is_some_and(|n| n.parse::<usize>().is_ok());
+    asm::is_ident(name) && !asm::is_keyword(name) && !generated
+}
+
+#[cfg(test)]
+mod disasm_tests {
+    use super::*;
+    use crate::vm::Op;
+
+    #[test]
+    fn fails_on_invalid_watermark() {
+        let data = "Rock\0[]\0\0".as_bytes().to_vec();
+        assert!(disassemble(&data).is_err());
+    }
+
+    #[test]
+    fn lists_memory_and_code() {
+        let mut data = "Rick\0[\"hi\\n\",null,2020]\0".as_bytes().to_vec();
+        data.extend(vec![
+            Op::Push.op(), 0, 0, 0, 0,
+            Op::Out.op(),
+            Op::Pop.op(), 0, 0, 0, 1,
+            Op::End.op(),
+        ]);
+
+        let expect = [
+            "@ memory",
+            ".mem m0 \"hi\\n\"                   @ 0 str",
+            ".mem m1 null                     @ 1 null",
+            ".mem m2 2020                     @ 2 int",
+            "",
+            "@ code",
+            "    push m0                      @ #0 \"hi\\n\"",
+            "    out                          @ #5",
+            "    pop m1                       @ #6 null",
+            "    end                          @ #11",
+            "",
+        ].join("\n");
+        assert_eq!(Ok(expect), disassemble(&data));
+    }
+
+    #[test]
+    fn marks_bad_instructions() {
+        let mem = vec![Obj::Int(1)];
+        let instructions = vec![
+            0xff,
+            Op::Push.op(), 0, 0, 0, 3,
+            Op::Pop.op(), 0,
+        ];
+        let listing = listing(&mem, &instructions, None);
+        assert_eq!(vec![
+            "    .byte 0xff                   @ #0 unknown opcode",
+            "    push m3                      @ #1 out of bounds",
+            "    .byte 0x02                   @ #6 truncated pop",
+            "    end                          @ #7",
+        ], listing.text.lines().skip(4).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn maps_lines_to_offsets() {
+        let mem = vec![Obj::Int(1)];
+        let instructions = vec![Op::Push.op(), 0, 0, 0, 0, Op::End.op()];
+        let listing = listing(&mem, &instructions, None);
+        assert_eq!(Some(5), listing.line(0));
+        assert_eq!(Some(6), listing.line(5));
+        assert_eq!(None, listing.line(1));
+        assert_eq!(Some(5), listing.offset(6));
+        assert_eq!(None, listing.offset(2));
+        assert_eq!(None, listing.offset(0));
+    }
+
+    #[test]
+    fn uses_debug_names_and_locations() {
+        let mem = vec![Obj::Int(1), Obj::Null, Obj::Null];
+        let instructions = vec![
+            Op::Push.op(), 0, 0, 0, 0,
+            Op::Pop.op(), 0, 0, 0, 1,
+            Op::Pop.op(), 0, 0, 0, 2,
+        ];
+        let debug = DebugInfo::from_json(&serde_json::json!({
+            "files": ["a.so"],
+            "locations": [[0, 0, 1, 5], [5, 0, 1, 5], [10, 0, 2, 1]],
+            "names": {"1": "age", "2": "m0"},
+        })).unwrap();
+        let listing = listing(&mem, &instructions, Some(&debug));
+        assert_eq!(vec![
+            ".mem m0 1                        @ 0 int",
+            ".mem age null                    @ 1 null",
+            ".mem m2 null                     @ 2 null",
+            "",
+            "@ code",
+            "    .loc \"a.so\" 1 5",
+            "    push m0                      @ #0 1",
+            "    pop age                      @ #5 null",
+            "    .loc \"a.so\" 2 1",
+            "    pop m2                       @ #10 null",
+        ], listing.text.lines().skip(1).collect::<Vec<_>>());
+        assert_eq!(Some(9), listing.line(5));
+    }
+}
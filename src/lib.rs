@@ -0,0 +1,36 @@
+mod util;
+pub mod vm;
+pub mod disasm;
+pub mod asm;
+pub mod debug;
+pub mod dap;
+
+pub use util::TResult;
+pub use vm::{VM, Obj, Key, Op, Outcome, Host, StdHost, MemHost};
+pub use vm::{ErrorKind, RuntimeError, LoadError, Problem, Tracer};
+pub use vm::{DebugInfo, Container};
+
+/// Read a compiled `.rk` executable from disk and prepare a VM to run it.
+pub fn load(src: &str) -> Result<VM, LoadError> {
+    let data = util::read_src_into_bytes(src).map_err(LoadError::Io)?;
+    VM::new(&data)
+}
+
+#[cfg(test)]
+mod load_tests {
+    use super::*;
+
+    #[test]
+    fn fails_if_file_not_found() {
+        assert_eq!(Some(LoadError::Io("failed to open executable")),
+                   load("examples/bytecode/not-found.rk").err());
+    }
+
+    #[test]
+    fn loads_executable() {
+        let mut vm = load("examples/bytecode/nop.rk").unwrap();
+        assert_eq!(&[Obj::Str(String::from("hello world"))], vm.mem());
+        vm.tick();
+        assert!(!vm.running());
+    }
+}
@@ -1,22 +1,195 @@
-#[macro_use] extern crate text_io;
+use std::fs;
+use std::io;
+use std::io::{BufRead, Write};
+use std::process;
 
-mod util;
-mod vm;
+mod cli;
+use cli::Command;
 
 fn main() {
-    let mut src = String::from("");
-    util::args(&mut src);
-    if src.len() == 0 {
-        util::exit_with_err("source path not specified");
+    process::exit(run());
+}
+
+fn run() -> i32 {
+    match cli::command() {
+        Err(code) => code,
+        Ok(Command::Run { src, vm, trace }) => execute(&src, &vm, &trace),
+        Ok(Command::Disasm { src }) => disasm(&src),
+        Ok(Command::Asm { src, out, debug_info }) => asm(&src, &out, debug_info),
+        Ok(Command::Convert { src, out, pool }) => convert(&src, &out, pool),
+        Ok(Command::Debug { src, vm }) => debug(&src, &vm),
+        Ok(Command::Dap) => dap(),
+    }
+}
+
+fn execute(src: &str, args: &cli::VmArgs, trace: &cli::TraceArgs) -> i32 {
+    if src.is_empty() {
+        cli::report_err("source path not specified");
+        return 1;
+    }
+
+    let mut vm = match rick::load(src) {
+        Err(err) => {
+            cli::report_err(&err.to_string());
+            return 1;
+        },
+        Ok(vm) => vm,
+    };
+    if let Err(problems) = vm.verify() {
+        cli::report_problems(&problems);
+        return 1;
+    }
+    if let Err(err) = cli::configure(&mut vm, args) {
+        cli::report_err(&err);
+        return 1;
+    }
+    match cli::tracer(trace) {
+        Err(err) => {
+            cli::report_err(&err);
+            return 1;
+        },
+        Ok(Some(tracer)) => vm.set_tracer(tracer),
+        Ok(None) => (),
+    }
+
+    let outcome = vm.boot();
+    cli::report_outcome(&outcome, vm.debug_info());
+    outcome.exit_code()
+}
+
+fn disasm(src: &str) -> i32 {
+    let listing = fs::read(src)
+        .map_err(|_| rick::LoadError::Io("failed to open executable"))
+        .and_then(|data| rick::disasm::disassemble(&data));
+    match listing {
+        Err(err) => {
+            cli::report_err(&err.to_string());
+            1
+        },
+        Ok(listing) => {
+            print!("{}", listing);
+            0
+        },
+    }
+}
+
+fn asm(src: &str, out: &str, debug_info: bool) -> i32 {
+    if same_file(src, out) {
+        cli::report_err("output would overwrite the assembly source");
+        return 1;
     }
+    let text = match fs::read_to_string(src) {
+        Err(_) => {
+            cli::report_err("failed to open assembly source");
+            return 1;
+        },
+        Ok(text) => text,
+    };
 
-    let data = util::read_src_into_bytes(&src);
-    util::exit_on_err(&data);
+    let bytecode = if debug_info {
+        rick::asm::assemble_with_debug_info(&text, src)
+    } else {
+        rick::asm::assemble(&text)
+    };
+    match bytecode {
+        Err(err) => {
+            cli::report_err(&err.to_string());
+            1
+        },
+        Ok(bytecode) => match fs::write(out, bytecode) {
+            Err(_) => {
+                cli::report_err("failed to write executable");
+                1
+            },
+            Ok(_) => 0,
+        },
+    }
+}
+
+fn same_file(a: &str, b: &str) -> bool {
+    match (fs::canonicalize(a), fs::canonicalize(b)) {
+        (Ok(a), Ok(b)) => a == b,
+        _ => false,
+    }
+}
+
+fn convert(src: &str, out: &str, pool: rick::vm::Pool) -> i32 {
+    let container = fs::read(src)
+        .map_err(|_| rick::LoadError::Io("failed to open executable"))
+        .and_then(|data| rick::Container::read(&data));
+    let mut container = match container {
+        Err(err) => {
+            cli::report_err(&err.to_string());
+            return 1;
+        },
+        Ok(container) => container,
+    };
+
+    // JSON would quietly turn int map keys into strings.
+    let int_keys = container.mem.iter().any(rick::Obj::has_int_keys);
+    if pool == rick::vm::Pool::Json && int_keys {
+        cli::report_err("int map keys in constants need --pool binary");
+        return 1;
+    }
+    container.pool = pool;
+    let bytecode = match container.to_bytes() {
+        Err(err) => {
+            cli::report_err(err);
+            return 1;
+        },
+        Ok(bytecode) => bytecode,
+    };
+    match fs::write(out, bytecode) {
+        Err(_) => {
+            cli::report_err("failed to write executable");
+            1
+        },
+        Ok(_) => 0,
+    }
+}
+
+fn debug(src: &str, args: &cli::VmArgs) -> i32 {
+    let mut vm = match rick::load(src) {
+        Err(err) => {
+            cli::report_err(&err.to_string());
+            return 1;
+        },
+        Ok(vm) => vm,
+    };
+    if let Err(problems) = vm.verify() {
+        cli::report_problems(&problems);
+        return 1;
+    }
+    if let Err(err) = cli::configure(&mut vm, args) {
+        cli::report_err(&err);
+        return 1;
+    }
 
-    let data = data.unwrap();
-    let vm = vm::VM::new(&data);
-    util::exit_on_err(&vm);
+    let mut debugger = rick::debug::Debugger::new(vm);
+    println!("{}", debugger.command("where").unwrap());
+    loop {
+        print!("(rick) ");
+        io::stdout().flush().ok();
 
-    vm.unwrap().boot();
+        let mut line = String::new();
+        match io::stdin().lock().read_line(&mut line) {
+            Ok(0) | Err(_) => return 0,
+            Ok(_) => (),
+        }
+        match debugger.command(&line) {
+            None => return 0,
+            Some(reply) => println!("{}", reply),
+        }
+    }
 }
 
+fn dap() -> i32 {
+    match rick::dap::serve(io::BufReader::new(io::stdin()),
+                           io::stdout().lock()) {
+        Err(err) => {
+            eprintln!("Error: {}", err);
+            1
+        },
+        Ok(_) => 0,
+    }
+}
@@ -1,36 +1,9 @@
-use std::process;
 use std::io::prelude::*;
 use std::fs::File;
 
-extern crate colored;
-use colored::*;
-
-extern crate argparse;
-use argparse::{ArgumentParser, Store};
-
 pub type TResult<T> = Result<T, &'static str>;
 
-pub fn args(src: &mut String) {
-    let mut ap = ArgumentParser::new();
-    ap.set_description("Execute SmallO bytecode");
-    ap.refer(src)
-        .add_argument("source", Store,
-                      "Path to SmallO assembly source code");
-    ap.parse_args_or_exit();
-}
-
-pub fn exit_on_err<T>(res: &TResult<T>) {
-    if let Err(err) = res {
-        exit_with_err(err);
-    }
-}
-
-pub fn exit_with_err(err: &'static str) {
-    println!("{}", format!("Error: {}", err).red());
-    process::exit(1);
-}
-
-pub fn read_src_into_bytes(src: &String) -> TResult<Vec<u8>> {
+pub fn read_src_into_bytes(src: &str) -> TResult<Vec<u8>> {
     let file = File::open(src);
     match file {
         Err(_) => Err("failed to open executable"),
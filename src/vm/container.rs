@@ -0,0 +1,318 @@
+extern crate serde_json;
+use serde_json::{json, Map, Value};
+
+use std::convert::{TryFrom, TryInto};
+
+use super::debug_info::DebugInfo;
+use super::error::LoadError;
+use super::obj::Obj;
+use super::pool;
+use super::vm_util;
+
+/// Version of the sectioned container format written by `Container::to_bytes`.
+pub const FORMAT_VERSION: u8 = 2;
+
+const CONSTANTS: u8 = 1;
+const CODE: u8 = 2;
+const DEBUG_INFO: u8 = 3;
+const METADATA: u8 = 4;
+const BINARY_CONSTANTS: u8 = 5;
+
+// Sections start right after the watermark and the version byte.
+const SECTIONS_START: usize = 6;
+
+type LoadResult<T> = Result<T, LoadError>;
+type EncodeResult<T> = Result<T, &'static str>;
+
+/// Pool says how the constants section of an executable is encoded.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum Pool {
+    #[default]
+    Json,
+    Binary,
+}
+
+/// Container is everything an executable holds, whichever format it came in.
+///
+/// Version 1 executables are the watermark `Rick\0`, a NUL-terminated JSON
+/// memory array (or an object holding it under "mem" next to "debug" info)
+/// and the instructions up to the end of the file.
+///
+/// Version 2 executables are the watermark, a version byte and a sequence of
+/// sections, each a kind byte, a big-endian u32 payload length and the
+/// payload:
+///
+/// | Kind | Section    | Payload                   |
+/// |:-----|:-----------|:--------------------------|
+/// | 1    | constants  | JSON memory array         |
+/// | 2    | code       | raw instructions          |
+/// | 3    | debug info | JSON `DebugInfo`          |
+/// | 4    | metadata   | JSON object               |
+/// | 5    | constants  | binary constant pool      |
+///
+/// Only the code section is required, and sections of unknown kinds are
+/// skipped so that newer files stay loadable where possible. Constants come
+/// in either encoding, but not both.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Container {
+    pub version: u8,
+    pub mem: Vec<Obj>,
+    pub instructions: Vec<u8>,
+    pub debug_info: Option<DebugInfo>,
+    pub metadata: Map<String, Value>,
+    pub pool: Pool,
+}
+
+impl Container {
+    /// Decode an executable of any supported format version. Errors point at
+    /// the offending byte.
+    pub fn read(bytecode: &[u8]) -> LoadResult<Self> {
+        if !vm_util::watermark_ok(bytecode) {
+            return Err(LoadError::at(0, "watermark check failed"));
+        }
+        // A v1 header is JSON text, which never starts with a control byte.
+        match bytecode.get(5) {
+            Some(&FORMAT_VERSION) => read_sections(bytecode),
+            Some(1..=8) => Err(LoadError::at(5, "unsupported format version")),
+            _ => Ok(Self {
+                version: 1,
+                mem: vm_util::read_mem(bytecode)?,
+                instructions: vm_util::read_instructions(bytecode)?,
+                debug_info: vm_util::read_debug_info(bytecode)?,
+                metadata: Map::new(),
+                pool: Pool::Json,
+            }),
+        }
+    }
+
+    /// Encode the container in the current format version. Fails on
+    /// constants that can't be stored and on sections too large for their
+    /// length field.
+    pub fn to_bytes(&self) -> EncodeResult<Vec<u8>> {
+        let mut bytecode = b"Rick\0".to_vec();
+        bytecode.push(FORMAT_VERSION);
+        match self.pool {
+            Pool::Json => {
+                let mem: Vec<Value> = self.mem.iter().map(Obj::to_json).collect();
+                write_section(&mut bytecode, CONSTANTS,
+                              &json_bytes(&json!(mem)))?;
+            },
+            Pool::Binary => write_section(&mut bytecode, BINARY_CONSTANTS,
+                                          &pool::encode(&self.mem)?)?,
+        }
+        write_section(&mut bytecode, CODE, &self.instructions)?;
+        if let Some(debug_info) = &self.debug_info {
+            write_section(&mut bytecode, DEBUG_INFO,
+                          &json_bytes(&debug_info.to_json()))?;
+        }
+        if !self.metadata.is_empty() {
+            write_section(&mut bytecode, METADATA,
+                          &json_bytes(&Value::Object(self.metadata.clone())))?;
+        }
+        Ok(bytecode)
+    }
+}
+
+fn read_sections(bytecode: &[u8]) -> LoadResult<Container> {
+    let mut container = Container {
+        version: FORMAT_VERSION,
+        ..Container::default()
+    };
+    let mut seen = Vec::new();
+    let mut code_start = bytecode.len();
+    let mut pos = SECTIONS_START;
+
+    while pos < bytecode.len() {
+        let header = match bytecode.get(pos..pos + 5) {
+            None => return Err(LoadError::at(pos, "truncated section header")),
+            Some(header) => header,
+        };
+        let kind = header[0];
+        let len = u32::from_be_bytes(header[1..].try_into().unwrap()) as usize;
+        let start = pos + 5;
+        let payload = match bytecode.get(start..start.saturating_add(len)) {
+            None => return Err(LoadError::at(pos, format!(
+                "truncated section: {} bytes declared, {} left",
+                len, bytecode.len() - start))),
+            Some(payload) => payload,
+        };
+
+        // Both encodings of the constants count as the same section.
+        let section = if kind == BINARY_CONSTANTS { CONSTANTS } else { kind };
+        if seen.contains(&section) {
+            return Err(LoadError::at(pos, "duplicate section"));
+        }
+        seen.push(section);
+
+        if kind == CODE {
+            code_start = start;
+        }
+        read_section(&mut container, kind, payload)
+            .map_err(|e| e.shifted(start))?;
+        pos = start + len;
+    }
+
+    if !seen.contains(&CODE) {
+        return Err(LoadError::at(bytecode.len(), "missing code section"));
+    }
+    if container.instructions.is_empty() {
+        return Err(LoadError::at(code_start, "empty instructions list"));
+    }
+    Ok(container)
+}
+
+// Error offsets are relative to the payload.
+fn read_section(container: &mut Container, kind: u8, payload: &[u8])
+    -> LoadResult<()> {
+    match kind {
+        CONSTANTS => container.mem = read_constants(payload)?,
+        BINARY_CONSTANTS => {
+            container.mem = pool::decode(payload)?;
+            container.pool = Pool::Binary;
+        },
+        CODE => container.instructions = payload.to_vec(),
+        DEBUG_INFO => {
+            let json = vm_util::parse_json(payload, "invalid debug info")?;
+            let info = DebugInfo::from_json(&json)
+                .map_err(|e| LoadError::at(0, e))?;
+            container.debug_info = Some(info);
+        },
+        METADATA => match vm_util::parse_json(payload, "invalid metadata")? {
+            Value::Object(metadata) => container.metadata = metadata,
+            _ => return Err(LoadError::at(0, "metadata is not a JSON object")),
+        },
+        _ => (),
+    }
+    Ok(())
+}
+
+fn read_constants(payload: &[u8]) -> LoadResult<Vec<Obj>> {
+    match vm_util::parse_json(payload, "invalid memory value")? {
+        Value::Array(_) => vm_util::json_into_obj(payload),
+        _ => Err(LoadError::at(0, "constants are not a JSON array")),
+    }
+}
+
+fn json_bytes(json: &Value) -> Vec<u8> {
+    serde_json::to_vec(json).unwrap()
+}
+
+fn write_section(bytecode: &mut Vec<u8>, kind: u8, payload: &[u8])
+    -> EncodeResult<()> {
+    let len = u32::try_from(payload.len())
+        .map_err(|_| "section too large for the container format")?;
+    bytecode.push(kind);
+    bytecode.extend_from_slice(&len.to_be_bytes());
+    bytecode.extend_from_slice(payload);
+    Ok(())
+}
+
+#[cfg(test)]
+mod container_tests {
+    use super::*;
+    use crate::vm::Op;
+
+    fn container() -> Container {
+        let mut metadata = Map::new();
+        metadata.insert(String::from("producer"), json!("test"));
+        Container {
+            version: FORMAT_VERSION,
+            mem: vec![Obj::Str(String::from("nul\0byte")), Obj::Int(42)],
+            instructions: vec![Op::Push.op(), 0, 0, 0, 1, Op::End.op()],
+            debug_info: Some(DebugInfo::default()),
+            metadata,
+            pool: Pool::Json,
+        }
+    }
+
+    #[test]
+    fn round_trips_v2() {
+        let container = container();
+        let bytecode = container.to_bytes().unwrap();
+        assert_eq!(Ok(container), Container::read(&bytecode));
+    }
+
+    #[test]
+    fn round_trips_binary_pool() {
+        let container = Container { pool: Pool::Binary, ..container() };
+        let bytecode = container.to_bytes().unwrap();
+        assert_eq!(Some(&BINARY_CONSTANTS), bytecode.get(6));
+        assert_eq!(Ok(container), Container::read(&bytecode));
+    }
+
+    #[test]
+    fn reads_v1() {
+        let mut data = "Rick\0[\"hi\", 1]\0".as_bytes().to_vec();
+        data.push(Op::End.op());
+        let container = Container::read(&data).unwrap();
+        assert_eq!(1, container.version);
+        assert_eq!(vec![Obj::Str(String::from("hi")), Obj::Int(1)],
+                   container.mem);
+        assert_eq!(vec![Op::End.op()], container.instructions);
+        assert_eq!(None, container.debug_info);
+    }
+
+    #[test]
+    fn writes_sections() {
+        let container = Container {
+            instructions: vec![Op::End.op()],
+            ..Container::default()
+        };
+        let mut expect = b"Rick\0\x02".to_vec();
+        expect.extend(&[CONSTANTS, 0, 0, 0, 2, b'[', b']']);
+        expect.extend(&[CODE, 0, 0, 0, 1, Op::End.op()]);
+        assert_eq!(expect, container.to_bytes().unwrap());
+    }
+
+    #[test]
+    fn skips_unknown_sections() {
+        let mut data = container().to_bytes().unwrap();
+        write_section(&mut data, 99, b"from the future").unwrap();
+        assert_eq!(Ok(container()), Container::read(&data));
+    }
+
+    #[test]
+    fn rejects_malformed_containers() {
+        let err = |offset, msg| Err(LoadError::at(offset, msg));
+        let data = container().to_bytes().unwrap();
+        let code = data.windows(5)
+            .position(|w| w == [CODE, 0, 0, 0, 6])
+            .unwrap();
+        assert_eq!(err(code, "truncated section: 6 bytes declared, 5 left"),
+                   Container::read(&data[..code + 10]));
+        assert_eq!(err(data.len(), "truncated section header"),
+                   Container::read(&[&data[..], &[CODE, 0]].concat()));
+
+        let mut dup = data.clone();
+        write_section(&mut dup, CODE, &[Op::End.op()]).unwrap();
+        assert_eq!(err(data.len(), "duplicate section"), Container::read(&dup));
+        let mut dup = data.clone();
+        let pool = pool::encode(&[]).unwrap();
+        write_section(&mut dup, BINARY_CONSTANTS, &pool).unwrap();
+        assert_eq!(err(data.len(), "duplicate section"), Container::read(&dup));
+
+        let mut no_code = b"Rick\0\x02".to_vec();
+        write_section(&mut no_code, CONSTANTS, b"[]").unwrap();
+        assert_eq!(err(13, "missing code section"), Container::read(&no_code));
+
+        let mut bad_mem = b"Rick\0\x02".to_vec();
+        write_section(&mut bad_mem, CONSTANTS, b"[1, 1e400]").unwrap();
+        match Container::read(&bad_mem) {
+            Err(LoadError::Malformed { offset, msg }) => {
+                assert_eq!(15, offset);
+                assert!(msg.ends_with("(slot 1)"));
+            },
+            other => panic!("expected a malformed pool, got {:?}", other),
+        }
+
+        let mut bad_pool = b"Rick\0\x02".to_vec();
+        write_section(&mut bad_pool, BINARY_CONSTANTS, &[1, 9]).unwrap();
+        assert_eq!(err(12, "unknown constant type tag"),
+                   Container::read(&bad_pool));
+
+        assert_eq!(err(5, "unsupported format version"),
+                   Container::read(b"Rick\0\x03"));
+        assert_eq!(err(0, "watermark check failed"),
+                   Container::read(b"Rock\0\x02"));
+    }
+}
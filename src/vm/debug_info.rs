@@ -0,0 +1,212 @@
+use std::collections::BTreeMap;
+use std::fmt;
+use std::path::Path;
+
+extern crate serde_json;
+use serde_json::{json, Value};
+
+use crate::util::TResult;
+
+/// Location ties the instruction at `offset` to a place in a source file.
+/// Lines and columns are 1-based.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Location {
+    pub offset: usize,
+    pub file: usize,
+    pub line: usize,
+    pub column: usize,
+}
+
+/// SourcePos is a resolved location, ready to be shown to a human.
+#[derive(Clone, Debug, PartialEq)]
+pub struct SourcePos<'a> {
+    pub file: &'a str,
+    pub line: usize,
+    pub column: usize,
+}
+
+impl fmt::Display for SourcePos<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}:{}:{}", self.file, self.line, self.column)
+    }
+}
+
+/// DebugInfo is the optional part of an executable that maps it back to the
+/// source it was built from: instruction offsets to source positions, memory
+/// slots to variable names and label names to offsets.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct DebugInfo {
+    pub files: Vec<String>,
+    pub locations: Vec<Location>,
+    pub names: BTreeMap<usize, String>,
+    pub labels: BTreeMap<String, usize>,
+}
+
+impl DebugInfo {
+    /// Read debug info from its JSON form:
+    ///
+    /// ```json
+    /// {
+    ///     "files": ["year_of_birth.so"],
+    ///     "locations": [[0, 0, 1, 3], [6, 0, 2, 3]],
+    ///     "names": {"1": "age"},
+    ///     "labels": {"loop": 0}
+    /// }
+    /// ```
+    ///
+    /// where each location is `[offset, file index, line, column]`.
+    pub fn from_json(json: &Value) -> TResult<Self> {
+        let invalid = "invalid debug info";
+        if !json.is_object() {
+            return Err(invalid);
+        }
+        let mut info = DebugInfo::default();
+
+        for file in json["files"].as_array().unwrap_or(&Vec::new()) {
+            info.files.push(file.as_str().ok_or(invalid)?.to_string());
+        }
+
+        for loc in json["locations"].as_array().unwrap_or(&Vec::new()) {
+            let fields: Vec<usize> = loc.as_array().ok_or(invalid)?
+                .iter()
+                .map(|v| v.as_u64().map(|n| n as usize))
+                .collect::<Option<_>>()
+                .ok_or(invalid)?;
+            match fields[..] {
+                [offset, file, line, column] if file < info.files.len() =>
+                    info.locations.push(Location { offset, file, line, column }),
+                _ => return Err(invalid),
+            }
+        }
+        info.locations.sort_by_key(|loc| loc.offset);
+
+        if let Some(names) = json["names"].as_object() {
+            for (mp, name) in names {
+                let mp = mp.parse::<usize>().map_err(|_| invalid)?;
+                info.names.insert(mp, name.as_str().ok_or(invalid)?.to_string());
+            }
+        }
+
+        if let Some(labels) = json["labels"].as_object() {
+            for (label, offset) in labels {
+                let offset = offset.as_u64().ok_or(invalid)? as usize;
+                info.labels.insert(label.clone(), offset);
+            }
+        }
+
+        Ok(info)
+    }
+
+    pub fn to_json(&self) -> Value {
+        let locations: Vec<Value> = self.locations.iter()
+            .map(|l| json!([l.offset, l.file, l.line, l.column]))
+            .collect();
+        let names: serde_json::Map<String, Value> = self.names.iter()
+            .map(|(mp, name)| (mp.to_string(), json!(name)))
+            .collect();
+        json!({
+            "files": self.files,
+            "locations": locations,
+            "names": names,
+            "labels": self.labels,
+        })
+    }
+
+    /// Find the source position of the instruction at `offset`. Instructions
+    /// without a location of their own inherit the closest one before them.
+    pub fn position(&self, offset: usize) -> Option<SourcePos<'_>> {
+        let i = self.locations.partition_point(|loc| loc.offset <= offset);
+        let loc = &self.locations[i.checked_sub(1)?];
+        Some(SourcePos {
+            file: self.files.get(loc.file)?,
+            line: loc.line,
+            column: loc.column,
+        })
+    }
+
+    /// Find the first instruction generated from `line` of `file`. The file
+    /// matches when either path ends with the other, so that absolute paths
+    /// from editors line up with the relative ones recorded at build time.
+    pub fn offset(&self, file: &str, line: usize) -> Option<usize> {
+        let file = self.files.iter().position(|f| same_file(f, file))?;
+        self.locations.iter()
+            .find(|loc| loc.file == file && loc.line == line)
+            .map(|loc| loc.offset)
+    }
+
+    pub fn name(&self, mp: usize) -> Option<&str> {
+        self.names.get(&mp).map(String::as_str)
+    }
+
+    pub fn slot(&self, name: &str) -> Option<usize> {
+        self.names.iter().find(|(_, n)| *n == name).map(|(mp, _)| *mp)
+    }
+}
+
+fn same_file(a: &str, b: &str) -> bool {
+    let (a, b) = (Path::new(a), Path::new(b));
+    a.ends_with(b) || b.ends_with(a)
+}
+
+#[cfg(test)]
+mod debug_info_tests {
+    use super::*;
+
+    fn info() -> DebugInfo {
+        DebugInfo::from_json(&json!({
+            "files": ["examples/smallo/year_of_birth.so"],
+            "locations": [[6, 0, 2, 3], [0, 0, 1, 3], [12, 0, 3, 3]],
+            "names": {"1": "age"},
+            "labels": {"start": 0},
+        })).unwrap()
+    }
+
+    #[test]
+    fn round_trips_through_json() {
+        let info = info();
+        assert_eq!(Ok(info.clone()), DebugInfo::from_json(&info.to_json()));
+    }
+
+    #[test]
+    fn rejects_malformed_json() {
+        assert!(DebugInfo::from_json(&json!({
+            "files": ["a.so"],
+            "locations": [[0, 1, 1, 1]],
+        })).is_err());
+        assert!(DebugInfo::from_json(&json!({ "names": {"x": "age"} }))
+                .is_err());
+        assert!(DebugInfo::from_json(&json!([])).is_err());
+        assert!(DebugInfo::from_json(&json!(42)).is_err());
+    }
+
+    #[test]
+    fn finds_positions() {
+        let info = info();
+        assert_eq!("examples/smallo/year_of_birth.so:1:3",
+                   info.position(0).unwrap().to_string());
+        assert_eq!(2, info.position(7).unwrap().line);
+        assert_eq!(3, info.position(40).unwrap().line);
+        assert_eq!(None, DebugInfo::default().position(0));
+
+        let mut info = info;
+        info.files.clear();
+        assert_eq!(None, info.position(0));
+    }
+
+    #[test]
+    fn finds_offsets() {
+        let info = info();
+        assert_eq!(Some(6), info.offset("/home/me/examples/smallo/year_of_birth.so", 2));
+        assert_eq!(Some(12), info.offset("year_of_birth.so", 3));
+        assert_eq!(None, info.offset("other.so", 3));
+        assert_eq!(None, info.offset("year_of_birth.so", 9));
+    }
+
+    #[test]
+    fn finds_names() {
+        let info = info();
+        assert_eq!(Some("age"), info.name(1));
+        assert_eq!(Some(1), info.slot("age"));
+        assert_eq!(None, info.slot("year"));
+    }
+}
@@ -0,0 +1,165 @@
+use std::error;
+use std::fmt;
+
+/// ErrorKind enumerates everything that can go wrong while the VM is running.
+#[derive(Clone, Debug, PartialEq)]
+pub enum ErrorKind {
+    StackUnderflow,
+    TypeMismatch { expected: &'static str, found: &'static str },
+    MemoryOutOfBounds { mp: usize, len: usize },
+    IpOutOfBounds(usize),
+    UnknownOpcode(u8),
+    TruncatedOperand,
+    InvalidCodeLocation(i64),
+    InvalidExitCode(i64),
+    CallDepthExceeded(usize),
+    EmptyCallStack,
+    InvalidConversion { value: String, to: &'static str },
+    DivisionByZero,
+    IndexOutOfBounds { index: i64, len: usize },
+    EmptyList,
+    KeyNotFound(String),
+    UnexpectedEof,
+    PermissionDenied(String),
+    InvalidFileMode(String),
+    InvalidHandle(usize),
+    WrongFileAccess(&'static str),
+    Io(String),
+}
+
+impl fmt::Display for ErrorKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ErrorKind::StackUnderflow =>
+                write!(f, "not enough values on the stack"),
+            ErrorKind::TypeMismatch { expected, found } =>
+                write!(f, "type mismatch: expected {}, found {}",
+                       expected, found),
+            ErrorKind::MemoryOutOfBounds { mp, len } =>
+                write!(f, "memory pointer {} out of bounds (memory size {})",
+                       mp, len),
+            ErrorKind::IpOutOfBounds(ip) =>
+                write!(f, "instruction pointer {} out of bounds", ip),
+            ErrorKind::UnknownOpcode(byte) =>
+                write!(f, "unknown opcode 0x{:02x}", byte),
+            ErrorKind::TruncatedOperand =>
+                write!(f, "operand truncated by end of instructions"),
+            ErrorKind::InvalidCodeLocation(location) =>
+                write!(f, "invalid code location: {}", location),
+            ErrorKind::InvalidExitCode(code) =>
+                write!(f, "invalid exit code: {}", code),
+            ErrorKind::CallDepthExceeded(depth) =>
+                write!(f, "maximum call depth of {} exceeded", depth),
+            ErrorKind::EmptyCallStack =>
+                write!(f, "return attempt with an empty call stack"),
+            ErrorKind::InvalidConversion { value, to } =>
+                write!(f, "failed to convert {:?} to {}", value, to),
+            ErrorKind::DivisionByZero => write!(f, "division by zero"),
+            ErrorKind::IndexOutOfBounds { index, len } =>
+                write!(f, "index {} out of bounds (list length {})", index, len),
+            ErrorKind::EmptyList => write!(f, "pop from an empty list"),
+            ErrorKind::KeyNotFound(key) => write!(f, "key {} not found in map", key),
+            ErrorKind::UnexpectedEof => write!(f, "unexpected end of input"),
+            ErrorKind::PermissionDenied(path) =>
+                write!(f, "permission denied: {:?}", path),
+            ErrorKind::InvalidFileMode(mode) =>
+                write!(f, "invalid file mode {:?}", mode),
+            ErrorKind::InvalidHandle(handle) =>
+                write!(f, "invalid or closed file handle {}", handle),
+            ErrorKind::WrongFileAccess(access) =>
+                write!(f, "file is not open for {}", access),
+            ErrorKind::Io(msg) => write!(f, "I/O failure: {}", msg),
+        }
+    }
+}
+
+/// RuntimeError is a fault raised by the VM along with the name of the opcode
+/// that raised it (if it got far enough to know one) and the offset of the
+/// faulty instruction.
+#[derive(Clone, Debug, PartialEq)]
+pub struct RuntimeError {
+    pub kind: ErrorKind,
+    pub opcode: Option<&'static str>,
+    pub ip: usize,
+}
+
+impl fmt::Display for RuntimeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.opcode {
+            Some(name) => write!(f, "[{}] {}", name, self.kind),
+            None => write!(f, "{}", self.kind),
+        }
+    }
+}
+
+impl error::Error for RuntimeError {}
+
+/// LoadError explains why an executable couldn't be loaded: either it couldn't
+/// be read at all, or its bytes stop making sense at `offset`.
+#[derive(Clone, Debug, PartialEq)]
+pub enum LoadError {
+    Io(&'static str),
+    Malformed { offset: usize, msg: String },
+}
+
+impl LoadError {
+    pub(crate) fn at<S: Into<String>>(offset: usize, msg: S) -> Self {
+        LoadError::Malformed { offset, msg: msg.into() }
+    }
+
+    /// Move the error by `base` bytes, for errors found inside a slice of
+    /// the executable.
+    pub(crate) fn shifted(self, base: usize) -> Self {
+        match self {
+            LoadError::Malformed { offset, msg } =>
+                LoadError::Malformed { offset: base + offset, msg },
+            err => err,
+        }
+    }
+}
+
+impl fmt::Display for LoadError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            LoadError::Io(msg) => write!(f, "{}", msg),
+            LoadError::Malformed { offset, msg } =>
+                write!(f, "{} at byte {}", msg, offset),
+        }
+    }
+}
+
+impl error::Error for LoadError {}
+
+#[cfg(test)]
+mod error_tests {
+    use super::*;
+
+    #[test]
+    fn displays_opcode_name() {
+        let err = RuntimeError {
+            kind: ErrorKind::TypeMismatch { expected: "int", found: "str" },
+            opcode: Some("add"),
+            ip: 3,
+        };
+        assert_eq!("[add] type mismatch: expected int, found str",
+                   err.to_string());
+    }
+
+    #[test]
+    fn displays_without_opcode() {
+        let err = RuntimeError {
+            kind: ErrorKind::UnknownOpcode(0x7e),
+            opcode: None,
+            ip: 0,
+        };
+        assert_eq!("unknown opcode 0x7e", err.to_string());
+    }
+
+    #[test]
+    fn displays_load_error_offset() {
+        let err = LoadError::at(2, "truncated section").shifted(6);
+        assert_eq!("truncated section at byte 8", err.to_string());
+        assert_eq!("failed to open executable",
+                   LoadError::Io("failed to open executable").to_string());
+    }
+}
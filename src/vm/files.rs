@@ -0,0 +1,271 @@
+use std::fs::{File, OpenOptions};
+use std::io;
+use std::io::{BufReader, Write};
+use std::path::{Component, Path, PathBuf};
+
+use super::error::ErrorKind;
+use super::host::{read_all_from, read_line_from};
+
+/// Files holds the files a program has opened and the directories it may
+/// touch. Programs get no file system access at all until directories are
+/// allowed for reading or writing, and then only to paths inside them.
+#[derive(Default)]
+pub struct Files {
+    read_dirs: Vec<PathBuf>,
+    write_dirs: Vec<PathBuf>,
+    // Handles index this table. Closed files leave a hole behind, so that a
+    // stale handle never reaches a file opened later.
+    open: Vec<Option<OpenFile>>,
+}
+
+enum OpenFile {
+    Read(BufReader<File>),
+    Write(File),
+}
+
+type FileResult<T> = Result<T, ErrorKind>;
+
+impl Files {
+    pub fn allow_read(&mut self, dir: &Path) -> io::Result<()> {
+        self.read_dirs.push(dir.canonicalize()?);
+        Ok(())
+    }
+
+    pub fn allow_write(&mut self, dir: &Path) -> io::Result<()> {
+        self.write_dirs.push(dir.canonicalize()?);
+        Ok(())
+    }
+
+    /// Open `path` for reading ("r"), writing ("w", truncating the file) or
+    /// appending ("a") and return its handle.
+    pub fn open(&mut self, path: &str, mode: &str) -> FileResult<usize> {
+        let (writing, truncate) = match mode {
+            "r" => (false, false),
+            "w" => (true, true),
+            "a" => (true, false),
+            _ => return Err(ErrorKind::InvalidFileMode(mode.to_string())),
+        };
+        let dirs = if writing { &self.write_dirs } else { &self.read_dirs };
+        let resolved = check(path, dirs)?;
+
+        // New files are created exclusively, which never follows a link that
+        // took their place in the meantime. Existing files are only truncated
+        // once it's certain that what got opened is what was checked.
+        let file = OpenOptions::new()
+            .read(!writing)
+            .write(writing)
+            .append(writing && !truncate)
+            .create_new(writing && !resolved.exists())
+            .open(&resolved)
+            .map_err(io_error)?;
+        if resolved.canonicalize().ok().as_ref() != Some(&resolved) {
+            return Err(ErrorKind::PermissionDenied(path.to_string()));
+        }
+        if truncate {
+            file.set_len(0).map_err(io_error)?;
+        }
+        self.open.push(Some(if writing {
+            OpenFile::Write(file)
+        } else {
+            OpenFile::Read(BufReader::new(file))
+        }));
+        Ok(self.open.len() - 1)
+    }
+
+    pub fn exists(&self, path: &str) -> FileResult<bool> {
+        Ok(check(path, &self.read_dirs)?.exists())
+    }
+
+    /// Read the next line without its line terminator. Returns `None` on EOF.
+    pub fn read_line(&mut self, handle: usize) -> FileResult<Option<String>> {
+        read_line_from(self.reader(handle)?).map_err(io_error)
+    }
+
+    /// Read everything up to EOF.
+    pub fn read_all(&mut self, handle: usize) -> FileResult<String> {
+        read_all_from(self.reader(handle)?).map_err(io_error)
+    }
+
+    pub fn write(&mut self, handle: usize, s: &str) -> FileResult<()> {
+        match self.file(handle)? {
+            OpenFile::Write(file) =>
+                file.write_all(s.as_bytes()).map_err(io_error),
+            OpenFile::Read(_) => Err(ErrorKind::WrongFileAccess("writing")),
+        }
+    }
+
+    pub fn close(&mut self, handle: usize) -> FileResult<()> {
+        self.file(handle)?;
+        self.open[handle] = None;
+        Ok(())
+    }
+
+    fn reader(&mut self, handle: usize) -> FileResult<&mut BufReader<File>> {
+        match self.file(handle)? {
+            OpenFile::Read(reader) => Ok(reader),
+            OpenFile::Write(_) => Err(ErrorKind::WrongFileAccess("reading")),
+        }
+    }
+
+    fn file(&mut self, handle: usize) -> FileResult<&mut OpenFile> {
+        match self.open.get_mut(handle) {
+            Some(Some(file)) => Ok(file),
+            _ => Err(ErrorKind::InvalidHandle(handle)),
+        }
+    }
+}
+
+// Resolve `path` and make sure it lies inside one of `dirs`. Symbolic links
+// are followed as far as the path exists, so they can't lead outside, and
+// dangling ones are refused, since opening them would create their target.
+fn check(path: &str, dirs: &[PathBuf]) -> FileResult<PathBuf> {
+    let denied = || ErrorKind::PermissionDenied(path.to_string());
+    let resolved = resolve(Path::new(path)).ok_or_else(denied)?;
+    if dirs.iter().any(|dir| resolved.starts_with(dir)) {
+        Ok(resolved)
+    } else {
+        Err(denied())
+    }
+}
+
+// Canonicalize the longest existing ancestor of `path` and append the rest,
+// which may only name plain files and directories.
+fn resolve(path: &Path) -> Option<PathBuf> {
+    let mut existing = path;
+    let mut rest = Vec::new();
+    loop {
+        if let Ok(dir) = existing.canonicalize() {
+            let mut resolved = dir;
+            for component in rest.iter().rev() {
+                match component {
+                    Component::Normal(name) => resolved.push(name),
+                    _ => return None,
+                }
+            }
+            return Some(resolved);
+        }
+        // It's there, but can't be resolved: a link to nowhere.
+        if existing.symlink_metadata().is_ok() {
+            return None;
+        }
+        rest.push(existing.components().next_back()?);
+        existing = match existing.parent()? {
+            p if p.as_os_str().is_empty() => Path::new("."),
+            p => p,
+        };
+    }
+}
+
+fn io_error(e: io::Error) -> ErrorKind {
+    ErrorKind::Io(e.to_string())
+}
+
+#[cfg(test)]
+mod files_tests {
+    use super::*;
+    use std::fs;
+
+    // A fresh scratch directory per test, so that tests can run in parallel.
+    fn scratch(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir()
+            .join(format!("rick-files-{}-{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    fn path(dir: &Path, name: &str) -> String {
+        dir.join(name).to_str().unwrap().to_string()
+    }
+
+    #[test]
+    fn denies_everything_by_default() {
+        let dir = scratch("default");
+        let mut files = Files::default();
+        let file = path(&dir, "file.txt");
+        assert_eq!(Err(ErrorKind::PermissionDenied(file.clone())),
+                   files.open(&file, "w"));
+        assert_eq!(Err(ErrorKind::PermissionDenied(file.clone())),
+                   files.exists(&file));
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn writes_appends_and_reads() {
+        let dir = scratch("rw");
+        let mut files = Files::default();
+        files.allow_read(&dir).unwrap();
+        files.allow_write(&dir).unwrap();
+        let file = path(&dir, "file.txt");
+        assert_eq!(Ok(false), files.exists(&file));
+
+        let h = files.open(&file, "w").unwrap();
+        files.write(h, "first\n").unwrap();
+        files.close(h).unwrap();
+        let h = files.open(&file, "a").unwrap();
+        files.write(h, "second\nthird").unwrap();
+        assert_eq!(Err(ErrorKind::WrongFileAccess("reading")),
+                   files.read_line(h));
+        files.close(h).unwrap();
+        assert_eq!(Ok(true), files.exists(&file));
+
+        let h = files.open(&file, "r").unwrap();
+        assert_eq!(Ok(Some(String::from("first"))), files.read_line(h));
+        assert_eq!(Ok(String::from("second\nthird")), files.read_all(h));
+        assert_eq!(Ok(None), files.read_line(h));
+        assert_eq!(Err(ErrorKind::WrongFileAccess("writing")),
+                   files.write(h, "more"));
+        files.close(h).unwrap();
+        assert_eq!(Err(ErrorKind::InvalidHandle(h)), files.close(h));
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn keeps_to_allowed_directories() {
+        let dir = scratch("allowed");
+        fs::create_dir(dir.join("inner")).unwrap();
+        let mut files = Files::default();
+        files.allow_read(&dir).unwrap();
+        files.allow_write(&dir.join("inner")).unwrap();
+
+        let outside = path(&dir, "file.txt");
+        assert_eq!(Err(ErrorKind::PermissionDenied(outside.clone())),
+                   files.open(&outside, "w"));
+        let escape = path(&dir, "inner/../file.txt");
+        assert_eq!(Err(ErrorKind::PermissionDenied(escape.clone())),
+                   files.open(&escape, "a"));
+        let missing = path(&dir, "inner/missing/../../file.txt");
+        assert_eq!(Err(ErrorKind::PermissionDenied(missing.clone())),
+                   files.open(&missing, "w"));
+        assert!(files.open(&path(&dir, "inner/file.txt"), "w").is_ok());
+        assert_eq!(Ok(true), files.exists(&path(&dir, "inner/file.txt")));
+        assert_eq!(Err(ErrorKind::InvalidFileMode(String::from("x"))),
+                   files.open(&path(&dir, "inner/file.txt"), "x"));
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn refuses_dangling_links() {
+        let dir = scratch("links");
+        fs::create_dir(dir.join("inner")).unwrap();
+        std::os::unix::fs::symlink(dir.join("target.txt"),
+                                   dir.join("inner/link")).unwrap();
+        let mut files = Files::default();
+        files.allow_read(&dir.join("inner")).unwrap();
+        files.allow_write(&dir.join("inner")).unwrap();
+
+        let link = path(&dir, "inner/link");
+        for mode in &["w", "a"] {
+            assert_eq!(Err(ErrorKind::PermissionDenied(link.clone())),
+                       files.open(&link, mode));
+        }
+        let beyond = path(&dir, "inner/link/file.txt");
+        assert_eq!(Err(ErrorKind::PermissionDenied(beyond.clone())),
+                   files.open(&beyond, "w"));
+        assert_eq!(Err(ErrorKind::PermissionDenied(link.clone())),
+                   files.exists(&link));
+        assert!(!dir.join("target.txt").exists());
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -0,0 +1,230 @@
+use std::cell::RefCell;
+use std::io;
+use std::io::{BufRead, Cursor, Read, Write};
+use std::rc::Rc;
+
+/// Host is the VM's window into the outside world. Every opcode that does
+/// input or output goes through it, so that the VM can be embedded and tested
+/// without touching the process' stdin and stdout.
+pub trait Host {
+    /// Read the next whitespace-delimited token. Returns `None` on EOF.
+    fn read_token(&mut self) -> io::Result<Option<String>>;
+
+    /// Read the next line without its line terminator. Returns `None` on EOF.
+    fn read_line(&mut self) -> io::Result<Option<String>>;
+
+    /// Read everything up to EOF. Returns an empty string at EOF.
+    ///
+    /// The default implementation collects the remaining lines, so line
+    /// terminators come back as `\n` and the last line always gets one.
+    /// Hosts that can read their input as is should do so instead.
+    fn read_all(&mut self) -> io::Result<String> {
+        let mut text = String::new();
+        while let Some(line) = self.read_line()? {
+            text.push_str(&line);
+            text.push('\n');
+        }
+        Ok(text)
+    }
+
+    fn write(&mut self, s: &str) -> io::Result<()>;
+
+    fn flush(&mut self) -> io::Result<()>;
+}
+
+/// StdHost talks to the process' stdin and stdout.
+pub struct StdHost;
+
+impl Host for StdHost {
+    fn read_token(&mut self) -> io::Result<Option<String>> {
+        read_token_from(&mut io::stdin().lock())
+    }
+
+    fn read_line(&mut self) -> io::Result<Option<String>> {
+        read_line_from(&mut io::stdin().lock())
+    }
+
+    fn read_all(&mut self) -> io::Result<String> {
+        read_all_from(&mut io::stdin().lock())
+    }
+
+    fn write(&mut self, s: &str) -> io::Result<()> {
+        io::stdout().write_all(s.as_bytes())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        io::stdout().flush()
+    }
+}
+
+/// MemHost reads scripted input from a string and collects output in memory.
+/// Keep a handle returned by `output` before handing the host over to the VM.
+pub struct MemHost {
+    input: Cursor<Vec<u8>>,
+    output: Rc<RefCell<String>>,
+}
+
+impl MemHost {
+    pub fn new(input: &str) -> Self {
+        Self {
+            input: Cursor::new(input.as_bytes().to_vec()),
+            output: Rc::new(RefCell::new(String::new())),
+        }
+    }
+
+    pub fn output(&self) -> Rc<RefCell<String>> {
+        Rc::clone(&self.output)
+    }
+}
+
+impl Host for MemHost {
+    fn read_token(&mut self) -> io::Result<Option<String>> {
+        read_token_from(&mut self.input)
+    }
+
+    fn read_line(&mut self) -> io::Result<Option<String>> {
+        read_line_from(&mut self.input)
+    }
+
+    fn read_all(&mut self) -> io::Result<String> {
+        read_all_from(&mut self.input)
+    }
+
+    fn write(&mut self, s: &str) -> io::Result<()> {
+        self.output.borrow_mut().push_str(s);
+        Ok(())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+// Tokens are read byte by byte straight out of the reader's buffer, so that
+// nothing past the delimiter gets consumed and lost for the next read.
+fn read_token_from<R: BufRead>(reader: &mut R) -> io::Result<Option<String>> {
+    let mut token = Vec::new();
+    loop {
+        let byte = match reader.fill_buf()?.first() {
+            None => break,
+            Some(b) => *b,
+        };
+        reader.consume(1);
+
+        if byte.is_ascii_whitespace() {
+            if token.is_empty() {
+                continue;
+            }
+            break;
+        }
+        token.push(byte);
+    }
+
+    if token.is_empty() {
+        return Ok(None);
+    }
+    String::from_utf8(token)
+        .map(Some)
+        .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+}
+
+pub(super) fn read_line_from<R: BufRead>(reader: &mut R)
+    -> io::Result<Option<String>> {
+    let mut line = String::new();
+    if reader.read_line(&mut line)? == 0 {
+        return Ok(None);
+    }
+
+    if line.ends_with('\n') {
+        line.pop();
+        if line.ends_with('\r') {
+            line.pop();
+        }
+    }
+    Ok(Some(line))
+}
+
+pub(super) fn read_all_from<R: Read>(reader: &mut R) -> io::Result<String> {
+    let mut text = String::new();
+    reader.read_to_string(&mut text)?;
+    Ok(text)
+}
+
+#[cfg(test)]
+mod host_tests {
+    use super::*;
+
+    #[test]
+    fn reads_tokens() {
+        let mut host = MemHost::new("  John Smith\n\n42");
+        assert_eq!(Some(String::from("John")), host.read_token().unwrap());
+        assert_eq!(Some(String::from("Smith")), host.read_token().unwrap());
+        assert_eq!(Some(String::from("42")), host.read_token().unwrap());
+        assert_eq!(None, host.read_token().unwrap());
+    }
+
+    #[test]
+    fn reads_lines() {
+        let mut host = MemHost::new("John Smith\r\n\nlast");
+        assert_eq!(Some(String::from("John Smith")), host.read_line().unwrap());
+        assert_eq!(Some(String::from("")), host.read_line().unwrap());
+        assert_eq!(Some(String::from("last")), host.read_line().unwrap());
+        assert_eq!(None, host.read_line().unwrap());
+    }
+
+    #[test]
+    fn token_leaves_rest_of_line() {
+        let mut host = MemHost::new("42 is the answer\n");
+        assert_eq!(Some(String::from("42")), host.read_token().unwrap());
+        assert_eq!(Some(String::from("is the answer")),
+                   host.read_line().unwrap());
+    }
+
+    #[test]
+    fn reads_everything_left() {
+        let mut host = MemHost::new("first\nsecond\nthird\n");
+        assert_eq!(Some(String::from("first")), host.read_line().unwrap());
+        assert_eq!("second\nthird\n", host.read_all().unwrap());
+        assert_eq!("", host.read_all().unwrap());
+    }
+
+    // A host that only knows how to read lines gets `read_all` for free.
+    struct Lines(Vec<&'static str>);
+
+    impl Host for Lines {
+        fn read_token(&mut self) -> io::Result<Option<String>> {
+            Ok(None)
+        }
+
+        fn read_line(&mut self) -> io::Result<Option<String>> {
+            if self.0.is_empty() {
+                return Ok(None);
+            }
+            Ok(Some(self.0.remove(0).to_string()))
+        }
+
+        fn write(&mut self, _: &str) -> io::Result<()> {
+            Ok(())
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn reads_everything_by_lines() {
+        let mut host = Lines(vec!["first", "second"]);
+        assert_eq!("first\nsecond\n", host.read_all().unwrap());
+        assert_eq!("", host.read_all().unwrap());
+    }
+
+    #[test]
+    fn collects_output() {
+        let mut host = MemHost::new("");
+        let output = host.output();
+        host.write("hello ").unwrap();
+        host.write("world").unwrap();
+        assert_eq!("hello world", *output.borrow());
+    }
+}
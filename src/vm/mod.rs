@@ -1,83 +1,252 @@
+use std::collections::BTreeMap;
+use std::convert::{TryFrom, TryInto};
 use std::io;
-use std::io::Write;
-use std::process;
-use std::convert::TryInto;
+use std::path::Path;
 
-extern crate colored;
-use colored::*;
+extern crate num_bigint;
+use num_bigint::BigInt;
 
-use crate::util::TResult;
+extern crate num_traits;
+use num_traits::{FromPrimitive, ToPrimitive, Zero};
 
 mod stack;
 use stack::Stack;
 
-mod op;
+pub(crate) mod op;
 use op::INSTRUCTION_SET;
+pub use op::Op;
 
 mod obj;
-use obj::Obj;
+pub use obj::{Obj, Key};
+use obj::Map;
 
-mod vm_util;
+mod host;
+pub use host::{Host, StdHost, MemHost};
+
+mod files;
+use files::Files;
+
+mod error;
+pub use error::{ErrorKind, RuntimeError, LoadError};
+
+mod outcome;
+pub use outcome::Outcome;
+
+mod trace;
+pub use trace::Tracer;
+
+mod verify;
+pub use verify::{verify, Problem};
+
+mod debug_info;
+pub use debug_info::{DebugInfo, Location, SourcePos};
+
+mod pool;
+
+mod container;
+pub use container::{Container, Pool, FORMAT_VERSION};
+
+pub(crate) mod vm_util;
+
+/// Default limit on the number of nested branches that haven't returned via
+/// `back` yet.
+pub const DEFAULT_MAX_CALL_DEPTH: usize = 1024;
+
+/// Operands of a numeric instruction after int/float promotion.
+enum Numbers {
+    Ints(i64, i64),
+    BigInts(BigInt, BigInt),
+    Floats(f64, f64),
+}
 
 pub struct VM {
     run: bool,
-    err: bool,
-    err_msg: String,
+    err: Option<RuntimeError>,
+    exit_code: Option<i32>,
 
     mem: Vec<Obj>,
     instructions: Vec<u8>,
+    debug_info: Option<DebugInfo>,
+    metadata: serde_json::Map<String, serde_json::Value>,
 
     ip: usize,
+    instruction_start: usize,
     opcode: u8,
     operand: u32,
 
     stack: Stack<Obj>,
+    calls: Stack<usize>,
+    max_call_depth: usize,
+    int_bools: bool,
+
+    host: Box<dyn Host>,
+    files: Files,
+    tracer: Option<Tracer>,
 }
 
 // Main methods.
 // These methods provide VM's basic functionality. Opcode execution is
 // impossible without these very important things.
 impl VM {
-    pub fn new(bytecode: &Vec<u8>) -> TResult<Self> {
-        if !vm_util::watermark_ok(bytecode) {
-            return Err("watermark check failed");
-        }
+    pub fn new(bytecode: &[u8]) -> Result<Self, LoadError> {
+        let container = Container::read(bytecode)?;
 
         Ok(Self{
             run: true,
-            err: false,
-            err_msg: String::from(""),
-            mem: vm_util::read_mem(bytecode)?,
-            instructions: vm_util::read_instructions(bytecode)?,
+            err: None,
+            exit_code: None,
+            mem: container.mem,
+            instructions: container.instructions,
+            debug_info: container.debug_info,
+            metadata: container.metadata,
             ip: 0,
+            instruction_start: 0,
             opcode: 0,
             operand: 0,
             stack: Stack::new(),
+            calls: Stack::new(),
+            max_call_depth: DEFAULT_MAX_CALL_DEPTH,
+            int_bools: false,
+            host: Box::new(StdHost),
+            files: Files::default(),
+            tracer: None,
         })
     }
 
-    pub fn boot(&mut self) {
-        while self.run && !self.err {
+    pub fn set_max_call_depth(&mut self, depth: usize) {
+        self.max_call_depth = depth;
+    }
+
+    /// Run in compatibility mode for bytecode written before booleans were a
+    /// type of their own: comparisons and logical instructions produce 1 and
+    /// 0, conditionals and logical instructions accept ints (conditionals
+    /// strings and floats too) and bool constants in memory become ints.
+    pub fn set_int_bools(&mut self, on: bool) {
+        self.int_bools = on;
+        if on {
+            for obj in self.mem.iter_mut() {
+                if let Obj::Bool(b) = obj {
+                    *obj = Obj::Int(*b as i64);
+                }
+            }
+        }
+    }
+
+    /// Let file instructions read files inside `dir`. Programs can't touch
+    /// the file system at all unless allowed to.
+    pub fn allow_read(&mut self, dir: &Path) -> io::Result<()> {
+        self.files.allow_read(dir)
+    }
+
+    /// Let file instructions create, write and append to files inside `dir`.
+    pub fn allow_write(&mut self, dir: &Path) -> io::Result<()> {
+        self.files.allow_write(dir)
+    }
+
+    pub fn set_host(&mut self, host: Box<dyn Host>) {
+        self.host = host;
+    }
+
+    pub fn set_tracer(&mut self, tracer: Tracer) {
+        self.tracer = Some(tracer);
+    }
+
+    pub fn running(&self) -> bool {
+        self.run && self.err.is_none()
+    }
+
+    pub fn ip(&self) -> usize {
+        self.ip
+    }
+
+    pub fn mem(&self) -> &[Obj] {
+        &self.mem
+    }
+
+    pub fn stack_top(&self) -> Option<&Obj> {
+        self.stack.peek()
+    }
+
+    /// Stack contents from bottom to top.
+    pub fn stack(&self) -> &[Obj] {
+        self.stack.as_slice()
+    }
+
+    /// Return addresses of the branches still waiting for `back`.
+    pub fn calls(&self) -> &[usize] {
+        self.calls.as_slice()
+    }
+
+    pub fn instructions(&self) -> &[u8] {
+        &self.instructions
+    }
+
+    /// Source map of the program, if it was built with one.
+    pub fn debug_info(&self) -> Option<&DebugInfo> {
+        self.debug_info.as_ref()
+    }
+
+    /// Free-form facts about the executable, such as the tool that built it.
+    pub fn metadata(&self) -> &serde_json::Map<String, serde_json::Value> {
+        &self.metadata
+    }
+
+    /// Overwrite a memory slot from the outside, e.g. from a debugger.
+    pub fn set_mem(&mut self, mp: usize, obj: Obj) -> Result<(), ErrorKind> {
+        let len = self.mem.len();
+        match self.mem.get_mut(mp) {
+            None => Err(ErrorKind::MemoryOutOfBounds { mp, len }),
+            Some(slot) => {
+                *slot = obj;
+                Ok(())
+            }
+        }
+    }
+
+    pub fn push_value(&mut self, obj: Obj) {
+        self.stack.push(obj);
+    }
+
+    pub fn pop_value(&mut self) -> Option<Obj> {
+        self.stack.pop()
+    }
+
+    pub fn fault(&self) -> Option<&RuntimeError> {
+        self.err.as_ref()
+    }
+
+    /// Check the loaded program for malformed instructions before running it.
+    pub fn verify(&self) -> Result<(), Vec<Problem>> {
+        verify(&self.mem, &self.instructions)
+    }
+
+    pub fn boot(&mut self) -> Outcome {
+        while self.running() {
             self.tick();
         }
-        self.exit();
+        if let Err(e) = self.host.flush() {
+            self.error(ErrorKind::Io(e.to_string()));
+        }
+        self.outcome().unwrap()
     }
 
     pub fn tick(&mut self) {
         self.fetch();
-        if self.err {
+        if self.err.is_some() {
             return;
         }
         self.decode();
-        if self.err {
+        if self.err.is_some() {
             return;
         }
         self.execute();
+        self.trace();
     }
 
     pub fn fetch(&mut self) {
+        self.instruction_start = self.ip;
         if self.ip_out_of_bounds() {
-            self.error("instruction pointer out of bounds");
+            self.raise(ErrorKind::IpOutOfBounds(self.ip), None);
         } else {
             self.opcode = self.instructions[self.ip];
             self.ip += 1;
@@ -86,7 +255,7 @@ impl VM {
 
     pub fn decode(&mut self) {
         if self.opcode_is_unknown() {
-            self.error("unknown opcode");
+            self.error(ErrorKind::UnknownOpcode(self.opcode));
         } else {
             self.decode_operand();
         }
@@ -96,6 +265,13 @@ impl VM {
         self.opcode_method()(self);
     }
 
+    fn trace(&mut self) {
+        if let Some(mut tracer) = self.tracer.take() {
+            tracer.log(self);
+            self.tracer = Some(tracer);
+        }
+    }
+
     fn ip_out_of_bounds(&self) -> bool {
         self.ip >= self.instructions.len()
     }
@@ -112,18 +288,31 @@ impl VM {
         INSTRUCTION_SET[self.opcode as usize].operand_offset
     }
 
-    fn exit(&self) {
-        if self.err {
-            let panic_msg = format!("Rick panicked at #{}!", self.ip).yellow();
-            let error_msg = format!("Error: {}.", self.err_msg).red();
-            println!("{}\n{}", panic_msg, error_msg);
+    /// Report how the program stopped, or `None` if it's still running.
+    pub fn outcome(&self) -> Option<Outcome> {
+        if let Some(err) = &self.err {
+            Some(Outcome::Fault(err.clone()))
+        } else if self.run {
+            None
+        } else if let Some(code) = self.exit_code {
+            Some(Outcome::Exit(code))
+        } else {
+            Some(Outcome::End)
         }
-        process::exit(self.err as i32);
     }
 
-    fn error(&mut self, msg: &str) {
-        self.err = true;
-        self.err_msg = String::from(msg);
+    // Raise a runtime error on behalf of the current opcode.
+    fn error(&mut self, kind: ErrorKind) {
+        let name = INSTRUCTION_SET.get(self.opcode as usize).map(|o| o.name);
+        self.raise(kind, name);
+    }
+
+    fn raise(&mut self, kind: ErrorKind, opcode: Option<&'static str>) {
+        self.err = Some(RuntimeError {
+            kind,
+            opcode,
+            ip: self.instruction_start,
+        });
     }
 
     fn decode_operand(&mut self) {
@@ -134,56 +323,337 @@ impl VM {
 
         let ip_with_offset = self.ip + operand_offset;
 
-        let operand_bytes = &self
-            .instructions[self.ip..ip_with_offset]
-            .try_into()
-            .expect("slice of incorrect length");
-        self.operand = u32::from_be_bytes(*operand_bytes);
+        let operand_bytes: Option<[u8; 4]> = self
+            .instructions
+            .get(self.ip..ip_with_offset)
+            .and_then(|bytes| bytes.try_into().ok());
+        match operand_bytes {
+            None => self.error(ErrorKind::TruncatedOperand),
+            Some(bytes) => {
+                self.operand = u32::from_be_bytes(bytes);
+                self.ip = ip_with_offset;
+            }
+        }
+    }
 
-        self.ip = ip_with_offset;
+    fn pop_obj(&mut self) -> Option<Obj> {
+        let top = self.stack.pop();
+        if top.is_none() {
+            self.error(ErrorKind::StackUnderflow);
+        }
+        top
     }
 
     fn binary_pop(&mut self) -> Option<(Obj, Obj)> {
         let top_b = self.stack.pop();
         let top_a = self.stack.pop();
 
-        if self.pop_is_empty(&top_b) || self.pop_is_empty(&top_a) {
+        if top_a.is_none() || top_b.is_none() {
+            self.error(ErrorKind::StackUnderflow);
             return None;
         }
         Some((top_a.unwrap(), top_b.unwrap()))
     }
 
-    fn pop_is_empty(&self, option: &Option<Obj>) -> bool {
-        if let None = option {
-            return true
+    fn type_mismatch(&mut self, expected: &'static str, found: &Obj) {
+        self.error(ErrorKind::TypeMismatch {
+            expected,
+            found: found.type_name(),
+        });
+    }
+
+    // Pop two numbers: both ints stay ints, ints of which either is big both
+    // become big, and anything with a float becomes floats.
+    fn binary_num_pop(&mut self) -> Option<Numbers> {
+        let (a, b) = self.binary_pop()?;
+        if let (Obj::Int(a), Obj::Int(b)) = (&a, &b) {
+            return Some(Numbers::Ints(*a, *b));
+        }
+        match (a.as_bigint(), b.as_bigint()) {
+            (Some(x), Some(y)) => Some(Numbers::BigInts(x, y)),
+            _ => match (a.as_float(), b.as_float()) {
+                (Some(x), Some(y)) => Some(Numbers::Floats(x, y)),
+                (None, _) => {
+                    self.type_mismatch("int or float", &a);
+                    None
+                },
+                (_, None) => {
+                    self.type_mismatch("int or float", &b);
+                    None
+                },
+            },
+        }
+    }
+
+    // Ints take the checked `int_op` fast path. When it fails, because the
+    // result overflows or the divisor is zero, `big_op` gets to either
+    // compute the result as a big int or report the error.
+    fn arithmetic(&mut self,
+                  int_op: fn(i64, i64) -> Option<i64>,
+                  big_op: fn(BigInt, BigInt) -> Result<BigInt, ErrorKind>,
+                  float_op: fn(f64, f64) -> Result<f64, ErrorKind>) {
+        let result = match self.binary_num_pop() {
+            None => return,
+            Some(Numbers::Ints(a, b)) => match int_op(a, b) {
+                Some(i) => Ok(Obj::Int(i)),
+                None => big_op(a.into(), b.into()).map(Obj::from_bigint),
+            },
+            Some(Numbers::BigInts(a, b)) => big_op(a, b).map(Obj::from_bigint),
+            Some(Numbers::Floats(x, y)) => float_op(x, y).map(Obj::Float),
+        };
+        match result {
+            Ok(obj) => self.stack.push(obj),
+            Err(kind) => self.error(kind),
+        }
+    }
+
+    fn comparison(&mut self,
+                  int_op: fn(&i64, &i64) -> bool,
+                  big_op: fn(&BigInt, &BigInt) -> bool,
+                  float_op: fn(&f64, &f64) -> bool) {
+        let result = match self.binary_num_pop() {
+            None => return,
+            Some(Numbers::Ints(a, b)) => int_op(&a, &b),
+            Some(Numbers::BigInts(a, b)) => big_op(&a, &b),
+            Some(Numbers::Floats(x, y)) => float_op(&x, &y),
+        };
+        self.push_bool(result);
+    }
+
+    // Booleans are ints in compatibility mode.
+    fn push_bool(&mut self, b: bool) {
+        let obj = if self.int_bools { Obj::Int(b as i64) } else { Obj::Bool(b) };
+        self.stack.push(obj);
+    }
+
+    fn as_logical(&self, obj: &Obj) -> Option<bool> {
+        match obj {
+            Obj::Bool(b) => Some(*b),
+            Obj::Int(i) if self.int_bools => Some(*i != 0),
+            _ => None,
+        }
+    }
+
+    fn logical_type(&self) -> &'static str {
+        if self.int_bools { "int" } else { "bool" }
+    }
+
+    fn logic(&mut self, op: fn(bool, bool) -> bool) {
+        if let Some((a, b)) = self.binary_pop() {
+            match (self.as_logical(&a), self.as_logical(&b)) {
+                (Some(a), Some(b)) => self.push_bool(op(a, b)),
+                (None, _) => self.type_mismatch(self.logical_type(), &a),
+                (_, None) => self.type_mismatch(self.logical_type(), &b),
+            }
+        }
+    }
+
+    fn pop_str(&mut self) -> Option<String> {
+        match self.pop_obj()? {
+            Obj::Str(s) => Some(s),
+            obj => {
+                self.type_mismatch("str", &obj);
+                None
+            },
+        }
+    }
+
+    fn pop_int(&mut self) -> Option<i64> {
+        match self.pop_obj()? {
+            Obj::Int(i) => Some(i),
+            obj => {
+                self.type_mismatch("int", &obj);
+                None
+            },
+        }
+    }
+
+    fn pop_list(&mut self) -> Option<Vec<Obj>> {
+        match self.pop_obj()? {
+            Obj::List(items) => Some(items),
+            obj => {
+                self.type_mismatch("list", &obj);
+                None
+            },
+        }
+    }
+
+    // Pop an index and the list underneath it, making sure the index is in
+    // bounds.
+    fn pop_list_index(&mut self) -> Option<(Vec<Obj>, usize)> {
+        let (list, index) = self.binary_pop()?;
+        let items = match list {
+            Obj::List(items) => items,
+            obj => {
+                self.type_mismatch("list", &obj);
+                return None;
+            },
+        };
+        let index = match index {
+            Obj::Int(i) => i,
+            obj => {
+                self.type_mismatch("int", &obj);
+                return None;
+            },
+        };
+        match usize::try_from(index) {
+            Ok(i) if i < items.len() => Some((items, i)),
+            _ => {
+                self.error(ErrorKind::IndexOutOfBounds { index, len: items.len() });
+                None
+            },
+        }
+    }
+
+    fn pop_map(&mut self) -> Option<Map> {
+        match self.pop_obj()? {
+            Obj::Map(map) => Some(map),
+            obj => {
+                self.type_mismatch("map", &obj);
+                None
+            },
+        }
+    }
+
+    // Pop a key and the map underneath it.
+    fn pop_map_key(&mut self) -> Option<(Map, Key)> {
+        let (map, key) = self.binary_pop()?;
+        let map = match map {
+            Obj::Map(map) => map,
+            obj => {
+                self.type_mismatch("map", &obj);
+                return None;
+            },
+        };
+        match Key::from_obj(&key) {
+            Some(key) => Some((map, key)),
+            None => {
+                self.type_mismatch("int or str", &key);
+                None
+            },
         }
-        false
     }
 
-    fn two_obj_as_int(&self, a: &Obj, b: &Obj) -> Option<(i64, i64)> {
-        if !a.is_int() || !b.is_int() {
+    fn mem_pointer(&mut self) -> Option<usize> {
+        let mp = self.operand as usize;
+        if mp >= self.mem.len() {
+            self.error(ErrorKind::MemoryOutOfBounds { mp, len: self.mem.len() });
             return None;
         }
-        Some((a.as_int().unwrap(), b.as_int().unwrap()))
+        Some(mp)
+    }
+
+    fn read_token(&mut self) -> Option<String> {
+        let token = self.host.flush().and_then(|_| self.host.read_token());
+        match token {
+            Err(e) => {
+                self.error(ErrorKind::Io(e.to_string()));
+                None
+            },
+            Ok(None) => {
+                self.error(ErrorKind::UnexpectedEof);
+                None
+            },
+            Ok(token) => token,
+        }
+    }
+
+    fn pop_file(&mut self) -> Option<usize> {
+        match self.pop_obj()? {
+            Obj::File(handle) => Some(handle),
+            obj => {
+                self.type_mismatch("file", &obj);
+                None
+            },
+        }
+    }
+
+    // Push the input `read` got and true, or only false at EOF, so that a
+    // `jmpf` right after can leave a reading loop.
+    fn read_or_eof(&mut self,
+                   read: fn(&mut dyn Host) -> std::io::Result<Option<String>>) {
+        let input = self.host.flush().and_then(|_| read(self.host.as_mut()));
+        match input {
+            Err(e) => self.error(ErrorKind::Io(e.to_string())),
+            Ok(None) => self.push_bool(false),
+            Ok(Some(s)) => {
+                self.stack.push(Obj::Str(s));
+                self.push_bool(true);
+            },
+        }
+    }
+
+    fn write(&mut self, s: &str) {
+        if let Err(e) = self.host.write(s) {
+            self.error(ErrorKind::Io(e.to_string()));
+        }
+    }
+
+    fn pop_location(&mut self) -> Option<usize> {
+        match self.pop_obj()? {
+            Obj::Int(i) if i >= 0 => Some(i as usize),
+            Obj::Int(i) => {
+                self.error(ErrorKind::InvalidCodeLocation(i));
+                None
+            },
+            obj => {
+                self.type_mismatch("int", &obj);
+                None
+            }
+        }
+    }
+
+    // Conditions must be bools, unless compatibility mode lets any truthy
+    // object through.
+    fn pop_condition(&mut self) -> Option<bool> {
+        let obj = self.pop_obj()?;
+        match obj {
+            Obj::Bool(b) => Some(b),
+            _ if !self.int_bools => {
+                self.type_mismatch("bool", &obj);
+                None
+            },
+            _ => match obj.to_bool() {
+                Some(b) => Some(b),
+                None => {
+                    self.type_mismatch("int or str", &obj);
+                    None
+                }
+            },
+        }
     }
 
-    fn binary_int_op(&mut self, name: &'static str, op: fn(i64, i64) -> i64) {
-        let objects = self.binary_pop();
-        if let None = objects {
-            self.error(&format!("[{}] not enough values on the stack", name));
+    fn conditional_jump(&mut self, when: bool) {
+        let location = self.pop_location();
+        if location.is_none() {
             return;
         }
 
-        let (obj_a, obj_b) = objects.unwrap();
-        let integers = self.two_obj_as_int(&obj_a, &obj_b);
-        if let None = integers {
-            self.error(&format!("[{}] type mismatch: {} & {}",
-                                name, obj_a, obj_b));
+        let condition = self.pop_condition();
+        if condition == Some(when) {
+            self.ip = location.unwrap();
+        }
+    }
+
+    fn conditional_branch(&mut self, when: bool) {
+        let location = self.pop_location();
+        if location.is_none() {
             return;
         }
 
-        let (a, b) = integers.unwrap();
-        self.stack.push(Obj::Int(op(a, b)));
+        let condition = self.pop_condition();
+        if condition == Some(when) {
+            self.branch(location.unwrap());
+        }
+    }
+
+    fn branch(&mut self, location: usize) {
+        if self.calls.len() >= self.max_call_depth {
+            self.error(ErrorKind::CallDepthExceeded(self.max_call_depth));
+            return;
+        }
+        self.calls.push(self.ip);
+        self.ip = location;
     }
 }
 
@@ -196,747 +666,2660 @@ impl VM {
     }
 
     fn push(&mut self) {
-        let mp = self.operand as usize;
-        if mp >= self.mem.len() {
-            self.error("[push] memory pointer out of bounds");
-        } else {
+        if let Some(mp) = self.mem_pointer() {
             self.stack.push(self.mem[mp].clone());
         }
     }
 
     fn pop(&mut self) {
-        let mp = self.operand as usize;
-        if mp >= self.mem.len() {
-            self.error("[pop] memory pointer out of bounds");
-        } else {
-            match self.stack.pop() {
-                None => self.error("[pop] pop attempt on an empty stack"),
-                Some(obj) => self.mem[mp] = obj
+        if let Some(mp) = self.mem_pointer() {
+            if let Some(obj) = self.pop_obj() {
+                self.mem[mp] = obj;
             }
         }
     }
 
     fn drop(&mut self) {
-        match self.stack.pop() {
-            None => self.error("[drop] pop attempt on an empty stack"),
-            Some(_) => ()
-        }
+        self.pop_obj();
     }
 
     fn ini(&mut self) {
-        io::stdout().flush().unwrap();
-        let result: Result<i64, _> = try_read!();
-        match result {
-            Err(_) => self.error("[ini] invalid string literal for conversion"),
-            Ok(i) => self.stack.push(Obj::Int(i))
+        if let Some(token) = self.read_token() {
+            match Obj::parse_int(&token) {
+                None => self.error(ErrorKind::InvalidConversion {
+                    value: token,
+                    to: "int",
+                }),
+                Some(obj) => self.stack.push(obj)
+            }
         }
     }
 
     fn ins(&mut self) {
-        io::stdout().flush().unwrap();
-        let s: String = read!();
-        self.stack.push(Obj::Str(s));
+        if let Some(token) = self.read_token() {
+            self.stack.push(Obj::Str(token));
+        }
     }
 
     fn out(&mut self) {
-        match self.stack.pop() {
-            None => self.error("[out] pop attempt on an empty stack"),
-            Some(obj) => print!("{}", obj)
+        if let Some(obj) = self.pop_obj() {
+            self.write(&obj.to_string());
         }
     }
 
     fn nl(&mut self) {
-        println!();
+        self.write("\n");
     }
 
     fn sti(&mut self) {
-        let top = self.stack.pop();
-        let obj: Obj;
-
-        if let Some(o) = top {
-            obj = o;
-        } else {
-            self.error("[sti] pop attempt on an empty stack");
-            return;
-        }
-
-        if let Obj::Str(string) = obj { 
-            match string.parse::<i64>() {
-                Ok(integer) => self.stack.push(Obj::Int(integer)),
-                Err(_) => self.error("[sti] failed to convert to int")
-            }
-        } else {
-            self.error("[sti] invalid stack top type (string expected)");
+        match self.pop_obj() {
+            None => (),
+            Some(Obj::Str(string)) => match Obj::parse_int(&string) {
+                Some(obj) => self.stack.push(obj),
+                None => self.error(ErrorKind::InvalidConversion {
+                    value: string,
+                    to: "int",
+                })
+            },
+            Some(obj) => self.type_mismatch("str", &obj),
         }
     }
 
     fn bool(&mut self) {
-        let top = self.stack.pop();
-        let obj: Obj;
-
-        if let Some(o) = top {
-            obj = o;
-        } else {
-            self.error("[bool] pop attempt on an empty stack");
-            return;
-        }
-
-        match obj.to_bool() {
-            Some(b) => self.stack.push(b),
-            None => self.error("[bool] attempt to convert null"),
+        if let Some(obj) = self.pop_obj() {
+            match obj.to_bool() {
+                Some(b) => self.push_bool(b),
+                None => self.type_mismatch("int or str", &obj),
+            }
         }
     }
 
     fn add(&mut self) {
-        self.binary_int_op("add", |a, b| a + b);
+        self.arithmetic(i64::checked_add, |a, b| Ok(a + b), |x, y| Ok(x + y));
     }
 
     fn sub(&mut self) {
-        self.binary_int_op("sub", |a, b| a - b);
+        self.arithmetic(i64::checked_sub, |a, b| Ok(a - b), |x, y| Ok(x - y));
     }
 
     fn mul(&mut self) {
-        self.binary_int_op("mul", |a, b| a * b);
+        self.arithmetic(i64::checked_mul, |a, b| Ok(a * b), |x, y| Ok(x * y));
     }
 
+    // Dividing by zero is a fault for floats too, rather than infinity.
     fn div(&mut self) {
-        self.binary_int_op("div", |a, b| a / b);
+        self.arithmetic(i64::checked_div, |a, b| if b.is_zero() {
+            Err(ErrorKind::DivisionByZero)
+        } else {
+            Ok(a / b)
+        }, |x, y| if y == 0.0 {
+            Err(ErrorKind::DivisionByZero)
+        } else {
+            Ok(x / y)
+        });
     }
 
     fn r#mod(&mut self) {
-        self.binary_int_op("mod", |a, b| a % b);
+        self.arithmetic(i64::checked_rem, |a, b| if b.is_zero() {
+            Err(ErrorKind::DivisionByZero)
+        } else {
+            Ok(a % b)
+        }, |x, y| if y == 0.0 {
+            Err(ErrorKind::DivisionByZero)
+        } else {
+            Ok(x % y)
+        });
     }
 
-    fn gth(&mut self) { self.binary_int_op("gth", |a, b| (a > b) as i64); }
+    fn gth(&mut self) { self.comparison(i64::gt, BigInt::gt, f64::gt); }
 
-    fn lth(&mut self) { self.binary_int_op("lth", |a, b| (a < b) as i64); }
+    fn lth(&mut self) { self.comparison(i64::lt, BigInt::lt, f64::lt); }
 
-    fn geq(&mut self) { self.binary_int_op("geq", |a, b| (a >= b) as i64); }
+    fn geq(&mut self) { self.comparison(i64::ge, BigInt::ge, f64::ge); }
 
-    fn leq(&mut self) { self.binary_int_op("leq", |a, b| (a <= b) as i64); }
+    fn leq(&mut self) { self.comparison(i64::le, BigInt::le, f64::le); }
 
-    fn and(&mut self) { self.binary_int_op("and",
-                                           |a, b| (a != 0 && b != 0) as i64); }
+    fn and(&mut self) {
+        self.logic(|a, b| a && b);
+    }
 
-    fn or(&mut self) { self.binary_int_op("or",
-                                           |a, b| (a != 0 || b != 0) as i64); }
+    fn or(&mut self) {
+        self.logic(|a, b| a || b);
+    }
 
     fn not(&mut self) {
-        let top = self.stack.pop();
-        if let None = top {
-            self.error(&format!("[not] not enough values on the stack"));
-            return;
+        if let Some(obj) = self.pop_obj() {
+            match self.as_logical(&obj) {
+                Some(b) => self.push_bool(!b),
+                None => self.type_mismatch(self.logical_type(), &obj),
+            }
         }
+    }
 
-        let obj = top.unwrap();
-        match obj.as_int() {
-            None => {
-                self.error(&format!("[not] type mismatch: expected integer"));
-                return;
-            },
-            Some(i) => self.stack.push(Obj::Int(!(i != 0) as i64))
+    fn eq(&mut self) {
+        if let Some((obj_a, obj_b)) = self.binary_pop() {
+            self.push_bool(obj_a.equal(&obj_b));
         }
     }
 
-    fn eq(&mut self) {
-        let objects = self.binary_pop();
-        if let None = objects {
-            self.error("[eq] not enough values on the stack");
-            return;
+    fn neq(&mut self) {
+        if let Some((obj_a, obj_b)) = self.binary_pop() {
+            self.push_bool(!obj_a.equal(&obj_b));
         }
+    }
 
-        let (obj_a, obj_b) = objects.unwrap();
-        self.stack.push(Obj::Int(obj_a.equal(&obj_b) as i64));
+    fn con(&mut self) {
+        if let Some((obj_a, obj_b)) = self.binary_pop() {
+            self.stack.push(Obj::Str(format!("{}{}", obj_a, obj_b)));
+        }
     }
 
-    fn neq(&mut self) {
-        let objects = self.binary_pop();
-        if let None = objects {
-            self.error("[eq] not enough values on the stack");
-            return;
+    fn jump(&mut self) {
+        if let Some(location) = self.pop_location() {
+            self.ip = location;
         }
+    }
 
-        let (obj_a, obj_b) = objects.unwrap();
-        self.stack.push(Obj::Int(!obj_a.equal(&obj_b) as i64));
+    fn jmpt(&mut self) {
+        self.conditional_jump(true);
     }
-}
 
-#[cfg(test)]
-mod vm_tests {
-    use super::*;
+    fn jmpf(&mut self) {
+        self.conditional_jump(false);
+    }
 
-    #[test]
-    fn can_create_new_instance() {
-        let data = "Rick\0[]\0\0".as_bytes().to_vec();
-        if let Err(_) = VM::new(&data) {
-            panic!("expected Ok");
+    fn br(&mut self) {
+        if let Some(location) = self.pop_location() {
+            self.branch(location);
         }
     }
 
-    #[test]
-    fn fails_on_unknown_instruction() {
-        let data: Vec<u8> = "Rick\0[]\0~".as_bytes().to_vec();
-        if let Ok(mut vm) = VM::new(&data) {
-            vm.tick();
-            assert!(vm.err);
-        } else {
-            panic!("expected Ok");
+    fn brt(&mut self) {
+        self.conditional_branch(true);
+    }
+
+    fn brf(&mut self) {
+        self.conditional_branch(false);
+    }
+
+    fn back(&mut self) {
+        match self.calls.pop() {
+            None => self.error(ErrorKind::EmptyCallStack),
+            Some(location) => self.ip = location,
         }
     }
-}
 
-#[cfg(test)]
-mod opcode_tests {
-    use super::*;
-    use op::Op;
+    fn err(&mut self) {
+        match self.pop_obj() {
+            None => (),
+            Some(Obj::Int(code)) => match i32::try_from(code) {
+                Ok(code) => {
+                    self.exit_code = Some(code);
+                    self.run = false;
+                },
+                Err(_) => self.error(ErrorKind::InvalidExitCode(code)),
+            },
+            Some(obj) => self.type_mismatch("int", &obj),
+        }
+    }
 
-    #[test]
-    fn push() {
-        let data: Vec<u8> = vec![
-            b'R', b'i', b'c', b'k', 0,
-            // mem: [42]
-            b'[', b'4', b'2', b']', 0,
-            Op::Push.op(), 0, 0, 0, 0,
-            Op::Push.op(), 0, 0, 0, 1,
-        ];
-        let vm = VM::new(&data);
-        if let Err(_) = vm {
-            panic!("expected Ok");
+    fn itf(&mut self) {
+        match self.pop_obj() {
+            None => (),
+            Some(Obj::Int(i)) => self.stack.push(Obj::Float(i as f64)),
+            Some(Obj::BigInt(b)) =>
+                self.stack.push(Obj::Float(b.to_f64().unwrap_or(f64::NAN))),
+            Some(obj) => self.type_mismatch("int", &obj),
         }
+    }
 
-        let mut vm = vm.unwrap();
-        vm.tick();
-        assert_eq!(Some(Obj::Int(42)), vm.stack.pop());
-        vm.tick();
-        assert!(vm.err);
+    // Floats are truncated toward zero; those without an int counterpart
+    // (NaN and infinities) fail to convert.
+    fn fti(&mut self) {
+        match self.pop_obj() {
+            None => (),
+            Some(Obj::Float(x)) => match BigInt::from_f64(x.trunc()) {
+                Some(b) => self.stack.push(Obj::from_bigint(b)),
+                None => self.error(ErrorKind::InvalidConversion {
+                    value: Obj::Float(x).to_string(),
+                    to: "int",
+                }),
+            },
+            Some(obj) => self.type_mismatch("float", &obj),
+        }
     }
 
-    #[test]
-    fn pop() {
-        let data: Vec<u8> = vec![
-            b'R', b'i', b'c', b'k', 0,
-            // mem: [0]
-            b'[', b'0', b']', 0,
+    fn stf(&mut self) {
+        match self.pop_obj() {
+            None => (),
+            Some(Obj::Str(string)) => match string.parse::<f64>() {
+                Ok(x) => self.stack.push(Obj::Float(x)),
+                Err(_) => self.error(ErrorKind::InvalidConversion {
+                    value: string,
+                    to: "float",
+                })
+            },
+            Some(obj) => self.type_mismatch("str", &obj),
+        }
+    }
+
+    fn fts(&mut self) {
+        match self.pop_obj() {
+            None => (),
+            Some(obj @ Obj::Float(_)) => self.stack.push(Obj::Str(obj.to_string())),
+            Some(obj) => self.type_mismatch("float", &obj),
+        }
+    }
+
+    fn lnew(&mut self) {
+        self.stack.push(Obj::List(Vec::new()));
+    }
+
+    fn lpush(&mut self) {
+        if let Some(obj) = self.pop_obj() {
+            if let Some(mut items) = self.pop_list() {
+                items.push(obj);
+                self.stack.push(Obj::List(items));
+            }
+        }
+    }
+
+    // Leaves the shortened list on the stack with the removed element on top.
+    fn lpop(&mut self) {
+        if let Some(mut items) = self.pop_list() {
+            match items.pop() {
+                None => self.error(ErrorKind::EmptyList),
+                Some(obj) => {
+                    self.stack.push(Obj::List(items));
+                    self.stack.push(obj);
+                },
+            }
+        }
+    }
+
+    fn lget(&mut self) {
+        if let Some((mut items, i)) = self.pop_list_index() {
+            self.stack.push(items.swap_remove(i));
+        }
+    }
+
+    fn lset(&mut self) {
+        if let Some(obj) = self.pop_obj() {
+            if let Some((mut items, i)) = self.pop_list_index() {
+                items[i] = obj;
+                self.stack.push(Obj::List(items));
+            }
+        }
+    }
+
+    fn llen(&mut self) {
+        if let Some(items) = self.pop_list() {
+            self.stack.push(Obj::Int(items.len() as i64));
+        }
+    }
+
+    // Step an iteration over a list: with a list and a position on the stack,
+    // push the list, the next position, the element and true, or only false
+    // once the list is exhausted. That makes `lnext` followed by a `jmpf` out
+    // of the loop the whole loop header.
+    fn lnext(&mut self) {
+        let (list, index) = match self.binary_pop() {
+            None => return,
+            Some(pair) => pair,
+        };
+        let items = match list {
+            Obj::List(items) => items,
+            obj => return self.type_mismatch("list", &obj),
+        };
+        match index {
+            Obj::Int(i) if i < 0 =>
+                self.error(ErrorKind::IndexOutOfBounds { index: i, len: items.len() }),
+            Obj::Int(i) => match items.get(i as usize).cloned() {
+                None => self.push_bool(false),
+                Some(obj) => {
+                    self.stack.push(Obj::List(items));
+                    self.stack.push(Obj::Int(i + 1));
+                    self.stack.push(obj);
+                    self.push_bool(true);
+                },
+            },
+            obj => self.type_mismatch("int", &obj),
+        }
+    }
+
+    fn mnew(&mut self) {
+        self.stack.push(Obj::Map(BTreeMap::new()));
+    }
+
+    fn mset(&mut self) {
+        if let Some(obj) = self.pop_obj() {
+            if let Some((mut map, key)) = self.pop_map_key() {
+                map.insert(key, obj);
+                self.stack.push(Obj::Map(map));
+            }
+        }
+    }
+
+    fn mget(&mut self) {
+        if let Some((mut map, key)) = self.pop_map_key() {
+            match map.remove(&key) {
+                Some(obj) => self.stack.push(obj),
+                None => self.error(ErrorKind::KeyNotFound(key.to_obj().to_literal())),
+            }
+        }
+    }
+
+    // Deleting a key that isn't there leaves the map as it is.
+    fn mdel(&mut self) {
+        if let Some((mut map, key)) = self.pop_map_key() {
+            map.remove(&key);
+            self.stack.push(Obj::Map(map));
+        }
+    }
+
+    fn mhas(&mut self) {
+        if let Some((map, key)) = self.pop_map_key() {
+            self.push_bool(map.contains_key(&key));
+        }
+    }
+
+    fn mkeys(&mut self) {
+        if let Some(map) = self.pop_map() {
+            self.stack.push(Obj::List(map.keys().map(Key::to_obj).collect()));
+        }
+    }
+
+    fn mlen(&mut self) {
+        if let Some(map) = self.pop_map() {
+            self.stack.push(Obj::Int(map.len() as i64));
+        }
+    }
+
+    // String instructions count in chars, not bytes.
+    fn slen(&mut self) {
+        if let Some(s) = self.pop_str() {
+            self.stack.push(Obj::Int(s.chars().count() as i64));
+        }
+    }
+
+    // Substring from the start index up to, but not including, the end index.
+    fn ssub(&mut self) {
+        let end = match self.pop_int() {
+            None => return,
+            Some(end) => end,
+        };
+        let start = match self.pop_int() {
+            None => return,
+            Some(start) => start,
+        };
+        if let Some(s) = self.pop_str() {
+            let len = s.chars().count();
+            if end < 0 || end as usize > len {
+                return self.error(ErrorKind::IndexOutOfBounds { index: end, len });
+            }
+            if start < 0 || start > end {
+                return self.error(ErrorKind::IndexOutOfBounds { index: start, len });
+            }
+            let sub = s.chars().skip(start as usize).take((end - start) as usize);
+            self.stack.push(Obj::Str(sub.collect()));
+        }
+    }
+
+    // Index of the first occurrence of the pattern, or -1 if there is none.
+    fn sfind(&mut self) {
+        let pattern = match self.pop_str() {
+            None => return,
+            Some(pattern) => pattern,
+        };
+        if let Some(s) = self.pop_str() {
+            let index = match s.find(&pattern) {
+                None => -1,
+                Some(byte) => s[..byte].chars().count() as i64,
+            };
+            self.stack.push(Obj::Int(index));
+        }
+    }
+
+    fn srep(&mut self) {
+        let to = match self.pop_str() {
+            None => return,
+            Some(to) => to,
+        };
+        let from = match self.pop_str() {
+            None => return,
+            Some(from) => from,
+        };
+        if let Some(s) = self.pop_str() {
+            self.stack.push(Obj::Str(s.replace(&from, &to)));
+        }
+    }
+
+    // An empty separator splits the string into its chars.
+    fn ssplit(&mut self) {
+        let sep = match self.pop_str() {
+            None => return,
+            Some(sep) => sep,
+        };
+        if let Some(s) = self.pop_str() {
+            let parts: Vec<Obj> = if sep.is_empty() {
+                s.chars().map(|c| Obj::Str(c.to_string())).collect()
+            } else {
+                s.split(&sep).map(|part| Obj::Str(part.to_string())).collect()
+            };
+            self.stack.push(Obj::List(parts));
+        }
+    }
+
+    fn sjoin(&mut self) {
+        let sep = match self.pop_str() {
+            None => return,
+            Some(sep) => sep,
+        };
+        if let Some(items) = self.pop_list() {
+            let mut parts = Vec::new();
+            for item in items {
+                match item {
+                    Obj::Str(s) => parts.push(s),
+                    obj => return self.type_mismatch("str", &obj),
+                }
+            }
+            self.stack.push(Obj::Str(parts.join(&sep)));
+        }
+    }
+
+    fn supper(&mut self) {
+        if let Some(s) = self.pop_str() {
+            self.stack.push(Obj::Str(s.to_uppercase()));
+        }
+    }
+
+    fn slower(&mut self) {
+        if let Some(s) = self.pop_str() {
+            self.stack.push(Obj::Str(s.to_lowercase()));
+        }
+    }
+
+    fn strim(&mut self) {
+        if let Some(s) = self.pop_str() {
+            self.stack.push(Obj::Str(s.trim().to_string()));
+        }
+    }
+
+    // Code point of a string holding exactly one char.
+    fn ord(&mut self) {
+        if let Some(s) = self.pop_str() {
+            let mut chars = s.chars();
+            match (chars.next(), chars.next()) {
+                (Some(c), None) => self.stack.push(Obj::Int(c as i64)),
+                _ => self.error(ErrorKind::InvalidConversion {
+                    value: s,
+                    to: "char code",
+                }),
+            }
+        }
+    }
+
+    fn chr(&mut self) {
+        if let Some(code) = self.pop_int() {
+            match u32::try_from(code).ok().and_then(char::from_u32) {
+                Some(c) => self.stack.push(Obj::Str(c.to_string())),
+                None => self.error(ErrorKind::InvalidConversion {
+                    value: code.to_string(),
+                    to: "char",
+                }),
+            }
+        }
+    }
+
+    fn rtok(&mut self) {
+        self.read_or_eof(|host| host.read_token());
+    }
+
+    fn rline(&mut self) {
+        self.read_or_eof(|host| host.read_line());
+    }
+
+    fn rall(&mut self) {
+        self.read_or_eof(|host| {
+            host.read_all().map(|s| Some(s).filter(|s| !s.is_empty()))
+        });
+    }
+
+    // Open the path under the mode on top of the stack: "r" to read, "w" to
+    // write from scratch or "a" to append.
+    fn fopen(&mut self) {
+        if let Some(mode) = self.pop_str() {
+            if let Some(path) = self.pop_str() {
+                match self.files.open(&path, &mode) {
+                    Ok(handle) => self.stack.push(Obj::File(handle)),
+                    Err(kind) => self.error(kind),
+                }
+            }
+        }
+    }
+
+    fn fread(&mut self) {
+        if let Some(handle) = self.pop_file() {
+            match self.files.read_all(handle) {
+                Ok(s) => self.stack.push(Obj::Str(s)),
+                Err(kind) => self.error(kind),
+            }
+        }
+    }
+
+    // Like `rline`, push the line and true, or only false at EOF.
+    fn fline(&mut self) {
+        if let Some(handle) = self.pop_file() {
+            match self.files.read_line(handle) {
+                Err(kind) => self.error(kind),
+                Ok(None) => self.push_bool(false),
+                Ok(Some(s)) => {
+                    self.stack.push(Obj::Str(s));
+                    self.push_bool(true);
+                },
+            }
+        }
+    }
+
+    fn fwrite(&mut self) {
+        if let Some(obj) = self.pop_obj() {
+            if let Some(handle) = self.pop_file() {
+                if let Err(kind) = self.files.write(handle, &obj.to_string()) {
+                    self.error(kind);
+                }
+            }
+        }
+    }
+
+    fn fclose(&mut self) {
+        if let Some(handle) = self.pop_file() {
+            if let Err(kind) = self.files.close(handle) {
+                self.error(kind);
+            }
+        }
+    }
+
+    fn fexists(&mut self) {
+        if let Some(path) = self.pop_str() {
+            match self.files.exists(&path) {
+                Ok(exists) => self.push_bool(exists),
+                Err(kind) => self.error(kind),
+            }
+        }
+    }
+
+    fn its(&mut self) {
+        match self.pop_obj() {
+            None => (),
+            Some(obj @ Obj::Int(_)) | Some(obj @ Obj::BigInt(_)) =>
+                self.stack.push(Obj::Str(obj.to_string())),
+            Some(obj) => self.type_mismatch("int", &obj),
+        }
+    }
+}
+
+#[cfg(test)]
+mod vm_tests {
+    use super::*;
+    use std::cell::RefCell;
+    use std::rc::Rc;
+
+    #[test]
+    fn can_create_new_instance() {
+        let data = "Rick\0[]\0\0".as_bytes().to_vec();
+        if VM::new(&data).is_err() {
+            panic!("expected Ok");
+        }
+    }
+
+    #[test]
+    fn loading_never_panics() {
+        let v2 = Container {
+            version: FORMAT_VERSION,
+            mem: vec![Obj::Str(String::from("hi")), Obj::Int(-7)],
+            instructions: vec![Op::Push.op(), 0, 0, 0, 0, Op::Out.op()],
+            debug_info: Some(DebugInfo::default()),
+            ..Container::default()
+        };
+        let samples = [
+            std::fs::read("examples/bytecode/year_of_birth.rk").unwrap(),
+            v2.to_bytes().unwrap(),
+            Container { pool: Pool::Binary, ..v2 }.to_bytes().unwrap(),
+        ];
+
+        for sample in samples.iter() {
+            for len in 0..sample.len() {
+                let _ = VM::new(&sample[..len]);
+            }
+            for i in 0..sample.len() {
+                for byte in [0x00, 0x02, 0x7f, 0x80, 0xff] {
+                    let mut data = sample.clone();
+                    data[i] = byte;
+                    let _ = VM::new(&data);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn boot_ends_normally() {
+        let data = "Rick\0[]\0\0".as_bytes().to_vec();
+        let mut vm = VM::new(&data).unwrap();
+        assert_eq!(Outcome::End, vm.boot());
+    }
+
+    #[test]
+    fn boot_exits_with_code() {
+        let data: Vec<u8> = vec![
+            b'R', b'i', b'c', b'k', 0,
+            // mem: [7]
+            b'[', b'7', b']', 0,
+            op::Op::Push.op(), 0, 0, 0, 0,
+            op::Op::Err.op(),
+        ];
+        let mut vm = VM::new(&data).unwrap();
+        assert_eq!(Outcome::Exit(7), vm.boot());
+    }
+
+    #[test]
+    fn boot_runs_example_program() {
+        let data = std::fs::read("examples/bytecode/year_of_birth.rk").unwrap();
+        let mut vm = VM::new(&data).unwrap();
+        let host = MemHost::new("30\n");
+        let output = host.output();
+        vm.set_host(Box::new(host));
+
+        assert_eq!(Outcome::End, vm.boot());
+        assert_eq!("Input your age: You were born in 1990\n", *output.borrow());
+    }
+
+    #[test]
+    fn boot_reports_fault() {
+        let data = "Rick\0[]\0~".as_bytes().to_vec();
+        let mut vm = VM::new(&data).unwrap();
+        assert_eq!(Outcome::Fault(RuntimeError {
+            kind: ErrorKind::UnknownOpcode(b'~'),
+            opcode: None,
+            ip: 0,
+        }), vm.boot());
+    }
+
+    #[test]
+    fn traces_executed_instructions() {
+        struct Sink(Rc<RefCell<Vec<u8>>>);
+
+        impl std::io::Write for Sink {
+            fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+                self.0.borrow_mut().write(buf)
+            }
+
+            fn flush(&mut self) -> std::io::Result<()> {
+                Ok(())
+            }
+        }
+
+        let data: Vec<u8> = vec![
+            b'R', b'i', b'c', b'k', 0,
+            // mem: ["hi", 1, 2, 3, 4, null]
+            b'[', b'"', b'h', b'i', b'"', b',', b'1', b',', b'2', b',',
+            b'3', b',', b'4', b',', b'n', b'u', b'l', b'l', b']', 0,
+            op::Op::Push.op(), 0, 0, 0, 1,
+            op::Op::Push.op(), 0, 0, 0, 2,
+            op::Op::Push.op(), 0, 0, 0, 3,
+            op::Op::Push.op(), 0, 0, 0, 4,
+            op::Op::Push.op(), 0, 0, 0, 0,
+            op::Op::Pop.op(), 0, 0, 0, 5,
+            op::Op::End.op(),
+        ];
+        let mut vm = VM::new(&data).unwrap();
+        let log = Rc::new(RefCell::new(Vec::new()));
+        let mut tracer = Tracer::new(Box::new(Sink(Rc::clone(&log))));
+        assert_eq!(Err(String::from("unknown opcode \"jmp\"")),
+                   tracer.set_opcodes(vec![String::from("jmp")]));
+        tracer.set_ip_range(15..=30);
+        tracer.set_mem_writes(true);
+        vm.set_tracer(tracer);
+
+        assert_eq!(Outcome::End, vm.boot());
+        assert_eq!([
+            "#15     push  m4       [.., 2, 3, 4]",
+            "#20     push  m0       [.., 3, 4, \"hi\"]",
+            "#25     pop   m5       [.., 2, 3, 4]  m5 <- \"hi\"",
+            "#30     end            [.., 2, 3, 4]",
+        ].join("\n") + "\n", String::from_utf8(log.borrow().clone()).unwrap());
+    }
+
+    #[test]
+    fn fails_on_truncated_operand() {
+        let data: Vec<u8> = vec![
+            b'R', b'i', b'c', b'k', 0,
+            // mem: [42]
+            b'[', b'4', b'2', b']', 0,
+            op::Op::Push.op(), 0, 0,
+        ];
+        let mut vm = VM::new(&data).unwrap();
+        vm.tick();
+        assert_eq!(Some(RuntimeError {
+            kind: ErrorKind::TruncatedOperand,
+            opcode: Some("push"),
+            ip: 0,
+        }), vm.err);
+    }
+
+    #[test]
+    fn fails_on_unknown_instruction() {
+        let data: Vec<u8> = "Rick\0[]\0~".as_bytes().to_vec();
+        if let Ok(mut vm) = VM::new(&data) {
+            vm.tick();
+            assert!(vm.err.is_some());
+        } else {
+            panic!("expected Ok");
+        }
+    }
+}
+
+#[cfg(test)]
+mod opcode_tests {
+    use super::*;
+    use op::Op;
+
+    #[test]
+    #[allow(clippy::redundant_pattern_matching)]
+    fn push() {
+        let data: Vec<u8> = vec![
+            b'R', b'i', b'c', b'k', 0,
+            // mem: [42]
+            b'[', b'4', b'2', b']', 0,
+            Op::Push.op(), 0, 0, 0, 0,
+            Op::Push.op(), 0, 0, 0, 1,
+        ];
+        let vm = VM::new(&data);
+        if let Err(_) = vm {
+            panic!("expected Ok");
+        }
+
+        let mut vm = vm.unwrap();
+        vm.tick();
+        assert_eq!(Some(Obj::Int(42)), vm.stack.pop());
+        vm.tick();
+        assert_eq!(ErrorKind::MemoryOutOfBounds { mp: 1, len: 1 },
+                   vm.err.unwrap().kind);
+    }
+
+    #[test]
+    #[allow(clippy::redundant_pattern_matching)]
+    fn pop() {
+        let data: Vec<u8> = vec![
+            b'R', b'i', b'c', b'k', 0,
+            // mem: [0]
+            b'[', b'0', b']', 0,
             Op::Pop.op(), 0, 0, 0, 0,
             Op::Pop.op(), 0, 0, 0, 0,
         ];
-        let vm = VM::new(&data);
-        if let Err(_) = vm {
-            panic!("expected Ok");
-        }
+        let vm = VM::new(&data);
+        if let Err(_) = vm {
+            panic!("expected Ok");
+        }
+
+        let mut vm = vm.unwrap();
+        vm.stack.push(Obj::Int(42));
+        vm.tick();
+        assert_eq!(Obj::Int(42), vm.mem[0]);
+        vm.tick();
+        assert!(vm.err.is_some());
+    }
+
+    #[test]
+    #[allow(clippy::redundant_pattern_matching)]
+    fn drop() {
+        let data: Vec<u8> = vec![
+            b'R', b'i', b'c', b'k', 0,
+            // mem: []
+            b'[', b']', 0,
+            Op::Drop.op(),
+            Op::Drop.op(),
+        ];
+        let vm = VM::new(&data);
+        if let Err(_) = vm {
+            panic!("expected Ok");
+        }
+
+        let mut vm = vm.unwrap();
+        vm.stack.push(Obj::Int(42));
+        vm.tick();
+        assert!(vm.stack.empty());
+        vm.tick();
+        assert!(vm.err.is_some());
+    }
+
+    #[test]
+    fn ini() {
+        let data: Vec<u8> = vec![
+            b'R', b'i', b'c', b'k', 0,
+            // mem: []
+            b'[', b']', 0,
+            Op::Ini.op(),
+            Op::Ini.op(),
+            Op::Ini.op(),
+        ];
+        let mut vm = VM::new(&data).unwrap();
+        vm.set_host(Box::new(MemHost::new("42\nfoo\n")));
+
+        vm.tick();
+        assert_eq!(Some(Obj::Int(42)), vm.stack.pop());
+
+        vm.tick();
+        assert!(vm.err.is_some());    // not an integer
+
+        vm.err = None;
+        vm.tick();
+        assert!(vm.err.is_some());    // end of input
+    }
+
+    #[test]
+    fn ins() {
+        let data: Vec<u8> = vec![
+            b'R', b'i', b'c', b'k', 0,
+            // mem: []
+            b'[', b']', 0,
+            Op::Ins.op(),
+            Op::Ins.op(),
+        ];
+        let mut vm = VM::new(&data).unwrap();
+        vm.set_host(Box::new(MemHost::new("  hello\n")));
+
+        vm.tick();
+        assert_eq!(Some(Obj::Str(String::from("hello"))), vm.stack.pop());
+
+        vm.tick();
+        assert!(vm.err.is_some());    // end of input
+    }
+
+    #[test]
+    fn out() {
+        let data: Vec<u8> = vec![
+            b'R', b'i', b'c', b'k', 0,
+            // mem: []
+            b'[', b']', 0,
+            Op::Out.op(),
+            Op::Out.op(),
+            Op::Out.op(),
+        ];
+        let mut vm = VM::new(&data).unwrap();
+        let host = MemHost::new("");
+        let output = host.output();
+        vm.set_host(Box::new(host));
+
+        vm.stack.push(Obj::Int(42));
+        vm.tick();
+        vm.stack.push(Obj::Str(String::from(" is the answer")));
+        vm.tick();
+        assert_eq!("42 is the answer", *output.borrow());
+
+        vm.tick();
+        assert!(vm.err.is_some());
+    }
+
+    #[test]
+    fn nl() {
+        let data: Vec<u8> = vec![
+            b'R', b'i', b'c', b'k', 0,
+            // mem: []
+            b'[', b']', 0,
+            Op::Nl.op(),
+        ];
+        let mut vm = VM::new(&data).unwrap();
+        let host = MemHost::new("");
+        let output = host.output();
+        vm.set_host(Box::new(host));
+
+        vm.tick();
+        assert_eq!("\n", *output.borrow());
+    }
+
+    #[test]
+    #[allow(clippy::redundant_pattern_matching)]
+    fn sti() {
+        let data: Vec<u8> = vec![
+            b'R', b'i', b'c', b'k', 0,
+            // mem: []
+            b'[', b']', 0,
+            Op::Sti.op(),
+            Op::Sti.op(),
+        ];
+        let vm = VM::new(&data);
+        if let Err(_) = vm {
+            panic!("expected Ok");
+        }
+
+        let mut vm = vm.unwrap();
+        vm.stack.push(Obj::Str(String::from("42")));
+        vm.tick();
+        assert_eq!(Some(Obj::Int(42)), vm.stack.pop());
+        vm.stack.push(Obj::Str(String::from("invalid")));
+        vm.tick();
+        assert!(vm.err.is_some());
+    }
+
+    #[test]
+    #[allow(clippy::redundant_pattern_matching)]
+    fn bool() {
+        let data: Vec<u8> = vec![
+            b'R', b'i', b'c', b'k', 0,
+            // mem: []
+            b'[', b']', 0,
+            Op::Bool.op(),
+            Op::Bool.op(),
+            Op::Bool.op(),
+            Op::Bool.op(),
+            Op::Bool.op(),
+        ];
+        let vm = VM::new(&data);
+        if let Err(_) = vm {
+            panic!("expected Ok");
+        }
+
+        let mut vm = vm.unwrap();
+        
+        vm.stack.push(Obj::Str(String::from("hello world")));
+        vm.tick();
+        assert_eq!(Some(Obj::Bool(true)), vm.stack.pop());
+
+        vm.stack.push(Obj::Str(String::from("")));
+        vm.tick();
+        assert_eq!(Some(Obj::Bool(false)), vm.stack.pop());
+
+        vm.stack.push(Obj::Int(42));
+        vm.tick();
+        assert_eq!(Some(Obj::Bool(true)), vm.stack.pop());
+
+        vm.stack.push(Obj::Int(0));
+        vm.tick();
+        assert_eq!(Some(Obj::Bool(false)), vm.stack.pop());
+
+        vm.tick();
+        assert!(vm.err.is_some());
+    }
+
+    #[test]
+    // In this method we also run destructive tests against binary_num_pop works. These tests will
+    // not be run in the rest of the binary operation tests.
+    fn add() {
+        let data: Vec<u8> = vec![
+            b'R', b'i', b'c', b'k', 0,
+            // mem: []
+            b'[', b']', 0,
+            Op::Add.op(),
+            Op::Add.op(),
+            Op::Add.op(),
+            Op::Add.op(),
+        ];
+        let mut vm = VM::new(&data).unwrap();
+
+        vm.stack.push(Obj::Int(40));
+        vm.stack.push(Obj::Int(2));
+        vm.tick();
+        assert_eq!(Some(Obj::Int(42)), vm.stack.pop());
+
+        vm.stack.push(Obj::Int(40));
+        vm.tick();
+        assert_eq!(ErrorKind::StackUnderflow, vm.err.unwrap().kind);
+
+        vm.err = None;
+        vm.stack.push(Obj::Int(40));
+        vm.stack.push(Obj::Str(String::from("hello world")));
+        vm.tick();
+        assert_eq!(Some(RuntimeError {
+            kind: ErrorKind::TypeMismatch {
+                expected: "int or float",
+                found: "str",
+            },
+            opcode: Some("add"),
+            ip: 2,
+        }), vm.err);
+
+        vm.err = None;
+        vm.stack.push(Obj::Int(i64::MAX));
+        vm.stack.push(Obj::Int(1));
+        vm.tick();
+        assert_eq!(Some(Obj::BigInt(BigInt::from(i64::MAX) + 1)), vm.stack.pop());
+    }
+
+    #[test]
+    #[allow(clippy::redundant_pattern_matching)]
+    fn sub() {
+        let data: Vec<u8> = vec![
+            b'R', b'i', b'c', b'k', 0,
+            // mem: []
+            b'[', b']', 0,
+            Op::Sub.op(),
+            Op::Sub.op(),
+            Op::Sub.op(),
+        ];
+        let vm = VM::new(&data);
+        if let Err(_) = vm {
+            panic!("expected Ok");
+        }
+
+        let mut vm = vm.unwrap();
+
+        vm.stack.push(Obj::Int(44));
+        vm.stack.push(Obj::Int(2));
+        vm.tick();
+        assert_eq!(Some(Obj::Int(42)), vm.stack.pop());
+
+        vm.stack.push(Obj::Int(i64::MIN));
+        vm.stack.push(Obj::Int(1));
+        vm.tick();
+        assert_eq!(Some(Obj::BigInt(BigInt::from(i64::MIN) - 1)), vm.stack.pop());
+
+        // Results that fit an int again become one.
+        vm.stack.push(Obj::BigInt(BigInt::from(i64::MIN) - 1));
+        vm.stack.push(Obj::Int(-1));
+        vm.tick();
+        assert_eq!(Some(Obj::Int(i64::MIN)), vm.stack.pop());
+    }
+
+    #[test]
+    #[allow(clippy::redundant_pattern_matching)]
+    fn mul() {
+        let data: Vec<u8> = vec![
+            b'R', b'i', b'c', b'k', 0,
+            // mem: []
+            b'[', b']', 0,
+            Op::Mul.op(),
+            Op::Mul.op(),
+            Op::Mul.op(),
+        ];
+        let vm = VM::new(&data);
+        if let Err(_) = vm {
+            panic!("expected Ok");
+        }
+
+        let mut vm = vm.unwrap();
+
+        vm.stack.push(Obj::Int(21));
+        vm.stack.push(Obj::Int(2));
+        vm.tick();
+        assert_eq!(Some(Obj::Int(42)), vm.stack.pop());
+
+        vm.stack.push(Obj::Int(i64::MAX));
+        vm.stack.push(Obj::Int(2));
+        vm.tick();
+        assert_eq!(Some(Obj::BigInt(BigInt::from(i64::MAX) * 2)), vm.stack.pop());
+    }
+
+    #[test]
+    #[allow(clippy::redundant_pattern_matching)]
+    fn div() {
+        let data: Vec<u8> = vec![
+            b'R', b'i', b'c', b'k', 0,
+            // mem: []
+            b'[', b']', 0,
+            Op::Div.op(),
+            Op::Div.op(),
+            Op::Div.op(),
+        ];
+        let vm = VM::new(&data);
+        if let Err(_) = vm {
+            panic!("expected Ok");
+        }
+
+        let mut vm = vm.unwrap();
+
+        vm.stack.push(Obj::Int(84));
+        vm.stack.push(Obj::Int(2));
+        vm.tick();
+        assert_eq!(Some(Obj::Int(42)), vm.stack.pop());
+
+        vm.stack.push(Obj::Int(84));
+        vm.stack.push(Obj::Int(0));
+        vm.tick();
+        assert_eq!(Some(ErrorKind::DivisionByZero), vm.err.map(|e| e.kind));
+        vm.err = None;
+
+        vm.stack.push(Obj::Int(i64::MIN));
+        vm.stack.push(Obj::Int(-1));
+        vm.tick();
+        assert_eq!(Some(Obj::BigInt(-BigInt::from(i64::MIN))), vm.stack.pop());
+    }
+
+    #[test]
+    fn r#mod() {
+        let data: Vec<u8> = vec![
+            b'R', b'i', b'c', b'k', 0,
+            // mem: []
+            b'[', b']', 0,
+            Op::Mod.op(),
+            Op::Mod.op(),
+            Op::Mod.op(),
+        ];
+        let mut vm = VM::new(&data).unwrap();
+
+        vm.stack.push(Obj::Int(84));
+        vm.stack.push(Obj::Int(2));
+        vm.tick();
+        assert_eq!(Some(Obj::Int(0)), vm.stack.pop());
+
+        vm.stack.push(Obj::Int(i64::MIN));
+        vm.stack.push(Obj::Int(-1));
+        vm.tick();
+        assert_eq!(Some(Obj::Int(0)), vm.stack.pop());
+
+        vm.stack.push(Obj::Int(84));
+        vm.stack.push(Obj::Int(0));
+        vm.tick();
+        assert_eq!(Some(ErrorKind::DivisionByZero), vm.err.map(|e| e.kind));
+    }
+
+    #[test]
+    #[allow(clippy::redundant_pattern_matching)]
+    fn gth() {
+        let data: Vec<u8> = vec![
+            b'R', b'i', b'c', b'k', 0,
+            // mem: []
+            b'[', b']', 0,
+            Op::Gth.op(),
+            Op::Gth.op(),
+            Op::Gth.op(),
+            Op::Gth.op(),
+        ];
+        let vm = VM::new(&data);
+        if let Err(_) = vm {
+            panic!("expected Ok");
+        }
+
+        let mut vm = vm.unwrap();
+
+        vm.stack.push(Obj::Int(84));
+        vm.stack.push(Obj::Int(2));
+        vm.tick();
+        assert_eq!(Some(Obj::Bool(true)), vm.stack.pop());
+
+        vm.stack.push(Obj::Int(0));
+        vm.stack.push(Obj::Int(2));
+        vm.tick();
+        assert_eq!(Some(Obj::Bool(false)), vm.stack.pop());
+    }
+
+    #[test]
+    #[allow(clippy::redundant_pattern_matching)]
+    fn lth() {
+        let data: Vec<u8> = vec![
+            b'R', b'i', b'c', b'k', 0,
+            // mem: []
+            b'[', b']', 0,
+            Op::Lth.op(),
+            Op::Lth.op(),
+            Op::Lth.op(),
+            Op::Lth.op(),
+        ];
+        let vm = VM::new(&data);
+        if let Err(_) = vm {
+            panic!("expected Ok");
+        }
+
+        let mut vm = vm.unwrap();
+
+        vm.stack.push(Obj::Int(84));
+        vm.stack.push(Obj::Int(2));
+        vm.tick();
+        assert_eq!(Some(Obj::Bool(false)), vm.stack.pop());
+
+        vm.stack.push(Obj::Int(0));
+        vm.stack.push(Obj::Int(2));
+        vm.tick();
+        assert_eq!(Some(Obj::Bool(true)), vm.stack.pop());
+    }
+
+    #[test]
+    #[allow(clippy::redundant_pattern_matching)]
+    fn geq() {
+        let data: Vec<u8> = vec![
+            b'R', b'i', b'c', b'k', 0,
+            // mem: []
+            b'[', b']', 0,
+            Op::Geq.op(),
+            Op::Geq.op(),
+            Op::Geq.op(),
+            Op::Geq.op(),
+            Op::Geq.op(),
+        ];
+        let vm = VM::new(&data);
+        if let Err(_) = vm {
+            panic!("expected Ok");
+        }
+
+        let mut vm = vm.unwrap();
+
+        vm.stack.push(Obj::Int(84));
+        vm.stack.push(Obj::Int(2));
+        vm.tick();
+        assert_eq!(Some(Obj::Bool(true)), vm.stack.pop());
+
+        vm.stack.push(Obj::Int(2));
+        vm.stack.push(Obj::Int(2));
+        vm.tick();
+        assert_eq!(Some(Obj::Bool(true)), vm.stack.pop());
+
+        vm.stack.push(Obj::Int(0));
+        vm.stack.push(Obj::Int(2));
+        vm.tick();
+        assert_eq!(Some(Obj::Bool(false)), vm.stack.pop());
+    }
+
+    #[test]
+    #[allow(clippy::redundant_pattern_matching)]
+    fn leq() {
+        let data: Vec<u8> = vec![
+            b'R', b'i', b'c', b'k', 0,
+            // mem: []
+            b'[', b']', 0,
+            Op::Leq.op(),
+            Op::Leq.op(),
+            Op::Leq.op(),
+            Op::Leq.op(),
+            Op::Leq.op(),
+        ];
+        let vm = VM::new(&data);
+        if let Err(_) = vm {
+            panic!("expected Ok");
+        }
+
+        let mut vm = vm.unwrap();
+
+        vm.stack.push(Obj::Int(84));
+        vm.stack.push(Obj::Int(2));
+        vm.tick();
+        assert_eq!(Some(Obj::Bool(false)), vm.stack.pop());
+
+        vm.stack.push(Obj::Int(2));
+        vm.stack.push(Obj::Int(2));
+        vm.tick();
+        assert_eq!(Some(Obj::Bool(true)), vm.stack.pop());
+
+        vm.stack.push(Obj::Int(0));
+        vm.stack.push(Obj::Int(2));
+        vm.tick();
+        assert_eq!(Some(Obj::Bool(true)), vm.stack.pop());
+    }
+
+    #[test]
+    #[allow(clippy::redundant_pattern_matching)]
+    fn and() {
+        let data: Vec<u8> = vec![
+            b'R', b'i', b'c', b'k', 0,
+            // mem: []
+            b'[', b']', 0,
+            Op::And.op(),
+            Op::And.op(),
+            Op::And.op(),
+            Op::And.op(),
+        ];
+        let vm = VM::new(&data);
+        if let Err(_) = vm {
+            panic!("expected Ok");
+        }
+
+        let mut vm = vm.unwrap();
+
+        vm.stack.push(Obj::Bool(false));
+        vm.stack.push(Obj::Bool(false));
+        vm.tick();
+        assert_eq!(Some(Obj::Bool(false)), vm.stack.pop());
+
+        vm.stack.push(Obj::Bool(false));
+        vm.stack.push(Obj::Bool(true));
+        vm.tick();
+        assert_eq!(Some(Obj::Bool(false)), vm.stack.pop());
+
+        vm.stack.push(Obj::Bool(true));
+        vm.stack.push(Obj::Bool(false));
+        vm.tick();
+        assert_eq!(Some(Obj::Bool(false)), vm.stack.pop());
+
+        vm.stack.push(Obj::Bool(true));
+        vm.stack.push(Obj::Bool(true));
+        vm.tick();
+        assert_eq!(Some(Obj::Bool(true)), vm.stack.pop());
+    }
+
+    #[test]
+    #[allow(clippy::redundant_pattern_matching)]
+    fn or() {
+        let data: Vec<u8> = vec![
+            b'R', b'i', b'c', b'k', 0,
+            // mem: []
+            b'[', b']', 0,
+            Op::Or.op(),
+            Op::Or.op(),
+            Op::Or.op(),
+            Op::Or.op(),
+        ];
+        let vm = VM::new(&data);
+        if let Err(_) = vm {
+            panic!("expected Ok");
+        }
+
+        let mut vm = vm.unwrap();
+
+        vm.stack.push(Obj::Bool(false));
+        vm.stack.push(Obj::Bool(false));
+        vm.tick();
+        assert_eq!(Some(Obj::Bool(false)), vm.stack.pop());
+
+        vm.stack.push(Obj::Bool(false));
+        vm.stack.push(Obj::Bool(true));
+        vm.tick();
+        assert_eq!(Some(Obj::Bool(true)), vm.stack.pop());
+
+        vm.stack.push(Obj::Bool(true));
+        vm.stack.push(Obj::Bool(false));
+        vm.tick();
+        assert_eq!(Some(Obj::Bool(true)), vm.stack.pop());
+
+        vm.stack.push(Obj::Bool(true));
+        vm.stack.push(Obj::Bool(true));
+        vm.tick();
+        assert_eq!(Some(Obj::Bool(true)), vm.stack.pop());
+    }
+
+    #[test]
+    #[allow(clippy::redundant_pattern_matching)]
+    fn not() {
+        let data: Vec<u8> = vec![
+            b'R', b'i', b'c', b'k', 0,
+            // mem: []
+            b'[', b']', 0,
+            Op::Not.op(),
+            Op::Not.op(),
+        ];
+        let vm = VM::new(&data);
+        if let Err(_) = vm {
+            panic!("expected Ok");
+        }
+
+        let mut vm = vm.unwrap();
+
+        vm.stack.push(Obj::Bool(false));
+        vm.tick();
+        assert_eq!(Some(Obj::Bool(true)), vm.stack.pop());
+
+        vm.stack.push(Obj::Bool(true));
+        vm.tick();
+        assert_eq!(Some(Obj::Bool(false)), vm.stack.pop());
+    }
+
+    #[test]
+    #[allow(clippy::redundant_pattern_matching)]
+    fn eq() {
+        let data: Vec<u8> = vec![
+            b'R', b'i', b'c', b'k', 0,
+            // mem: []
+            b'[', b']', 0,
+            Op::Eq.op(),
+            Op::Eq.op(),
+            Op::Eq.op(),
+        ];
+        let vm = VM::new(&data);
+        if let Err(_) = vm {
+            panic!("expected Ok");
+        }
+
+        let mut vm = vm.unwrap();
+
+        vm.stack.push(Obj::Str(String::from("let magic = ")));
+        vm.stack.push(Obj::Int(42));
+        vm.tick();
+        assert_eq!(Some(Obj::Bool(false)), vm.stack.pop());
+
+        vm.stack.push(Obj::Str(String::from("hello world")));
+        vm.stack.push(Obj::Str(String::from("hello world")));
+        vm.tick();
+        assert_eq!(Some(Obj::Bool(true)), vm.stack.pop());
+
+        vm.stack.push(Obj::Int(5));
+        vm.stack.push(Obj::Int(5));
+        vm.tick();
+        assert_eq!(Some(Obj::Bool(true)), vm.stack.pop());
+    }
+
+    #[test]
+    #[allow(clippy::redundant_pattern_matching)]
+    fn neq() {
+        let data: Vec<u8> = vec![
+            b'R', b'i', b'c', b'k', 0,
+            // mem: []
+            b'[', b']', 0,
+            Op::Neq.op(),
+            Op::Neq.op(),
+            Op::Neq.op(),
+        ];
+        let vm = VM::new(&data);
+        if let Err(_) = vm {
+            panic!("expected Ok");
+        }
+
+        let mut vm = vm.unwrap();
+
+        vm.stack.push(Obj::Str(String::from("let magic = ")));
+        vm.stack.push(Obj::Int(42));
+        vm.tick();
+        assert_eq!(Some(Obj::Bool(true)), vm.stack.pop());
+
+        vm.stack.push(Obj::Str(String::from("hello world")));
+        vm.stack.push(Obj::Str(String::from("hello world")));
+        vm.tick();
+        assert_eq!(Some(Obj::Bool(false)), vm.stack.pop());
+
+        vm.stack.push(Obj::Int(5));
+        vm.stack.push(Obj::Int(5));
+        vm.tick();
+        assert_eq!(Some(Obj::Bool(false)), vm.stack.pop());
+    }
+
+    #[test]
+    fn con() {
+        let data: Vec<u8> = vec![
+            b'R', b'i', b'c', b'k', 0,
+            // mem: []
+            b'[', b']', 0,
+            Op::Con.op(),
+            Op::Con.op(),
+            Op::Con.op(),
+        ];
+        let mut vm = VM::new(&data).unwrap();
+
+        vm.stack.push(Obj::Str(String::from("let magic = ")));
+        vm.stack.push(Obj::Int(42));
+        vm.tick();
+        assert_eq!(Some(Obj::Str(String::from("let magic = 42"))),
+                   vm.stack.pop());
+
+        vm.stack.push(Obj::Str(String::from("hello ")));
+        vm.stack.push(Obj::Str(String::from("world")));
+        vm.tick();
+        assert_eq!(Some(Obj::Str(String::from("hello world"))),
+                   vm.stack.pop());
+
+        vm.stack.push(Obj::Int(42));
+        vm.tick();
+        assert!(vm.err.is_some());
+    }
+
+    #[test]
+    fn jump() {
+        let data: Vec<u8> = vec![
+            b'R', b'i', b'c', b'k', 0,
+            // mem: []
+            b'[', b']', 0,
+            Op::Jum.op(),
+            Op::Jum.op(),
+            Op::Jum.op(),
+        ];
+        let mut vm = VM::new(&data).unwrap();
+
+        vm.stack.push(Obj::Int(2));
+        vm.tick();
+        assert_eq!(2, vm.ip);
+        assert!(vm.stack.empty());
+
+        vm.stack.push(Obj::Int(-1));
+        vm.tick();
+        assert!(vm.err.is_some());
+
+        vm.err = None;
+        vm.ip = 0;
+        vm.stack.push(Obj::Str(String::from("nowhere")));
+        vm.tick();
+        assert!(vm.err.is_some());
+    }
+
+    #[test]
+    fn jmpt() {
+        let data: Vec<u8> = vec![
+            b'R', b'i', b'c', b'k', 0,
+            // mem: []
+            b'[', b']', 0,
+            Op::Jmpt.op(),
+            Op::Jmpt.op(),
+            Op::Jmpt.op(),
+        ];
+        let mut vm = VM::new(&data).unwrap();
+
+        vm.stack.push(Obj::Bool(false));
+        vm.stack.push(Obj::Int(0));
+        vm.tick();
+        assert_eq!(1, vm.ip);
+        assert!(vm.stack.empty());
+
+        vm.stack.push(Obj::Bool(true));
+        vm.stack.push(Obj::Int(0));
+        vm.tick();
+        assert_eq!(0, vm.ip);
+
+        vm.stack.push(Obj::Int(1));
+        vm.tick();
+        assert!(vm.err.is_some());    // no condition under the location
+    }
+
+    #[test]
+    fn jmpf() {
+        let data: Vec<u8> = vec![
+            b'R', b'i', b'c', b'k', 0,
+            // mem: []
+            b'[', b']', 0,
+            Op::Jmpf.op(),
+            Op::Jmpf.op(),
+            Op::Jmpf.op(),
+        ];
+        let mut vm = VM::new(&data).unwrap();
+
+        vm.stack.push(Obj::Bool(true));
+        vm.stack.push(Obj::Int(0));
+        vm.tick();
+        assert_eq!(1, vm.ip);
+        assert!(vm.stack.empty());
+
+        vm.stack.push(Obj::Bool(false));
+        vm.stack.push(Obj::Int(0));
+        vm.tick();
+        assert_eq!(0, vm.ip);
+
+        vm.stack.push(Obj::Int(1));
+        vm.stack.push(Obj::Int(0));
+        vm.tick();
+        assert!(vm.err.is_some());    // ints are not conditions
+    }
+
+    #[test]
+    fn br() {
+        let data: Vec<u8> = vec![
+            b'R', b'i', b'c', b'k', 0,
+            // mem: []
+            b'[', b']', 0,
+            Op::Nl.op(),
+            Op::Br.op(),
+            Op::Br.op(),
+        ];
+        let mut vm = VM::new(&data).unwrap();
+
+        vm.ip = 1;
+        vm.stack.push(Obj::Int(0));
+        vm.tick();
+        assert_eq!(0, vm.ip);
+        assert!(vm.stack.empty());
+        assert_eq!(Some(&2), vm.calls.peek());
+
+        vm.ip = 2;
+        vm.tick();
+        assert!(vm.err.is_some());
+    }
+
+    #[test]
+    fn br_exceeding_max_call_depth() {
+        let data: Vec<u8> = vec![
+            b'R', b'i', b'c', b'k', 0,
+            // mem: []
+            b'[', b']', 0,
+            Op::Br.op(),
+        ];
+        let mut vm = VM::new(&data).unwrap();
+        vm.set_max_call_depth(2);
+
+        vm.stack.push(Obj::Int(0));
+        vm.tick();
+        vm.stack.push(Obj::Int(0));
+        vm.tick();
+        assert_eq!(2, vm.calls.len());
+
+        vm.stack.push(Obj::Int(0));
+        vm.tick();
+        assert!(vm.err.is_some());
+        assert_eq!(2, vm.calls.len());
+    }
+
+    #[test]
+    fn brt() {
+        let data: Vec<u8> = vec![
+            b'R', b'i', b'c', b'k', 0,
+            // mem: []
+            b'[', b']', 0,
+            Op::Brt.op(),
+            Op::Brt.op(),
+        ];
+        let mut vm = VM::new(&data).unwrap();
+
+        vm.stack.push(Obj::Bool(false));
+        vm.stack.push(Obj::Int(0));
+        vm.tick();
+        assert_eq!(1, vm.ip);
+        assert!(vm.stack.empty());
+
+        vm.stack.push(Obj::Bool(true));
+        vm.stack.push(Obj::Int(0));
+        vm.tick();
+        assert_eq!(0, vm.ip);
+        assert_eq!(Some(&2), vm.calls.peek());
+    }
+
+    #[test]
+    fn brf() {
+        let data: Vec<u8> = vec![
+            b'R', b'i', b'c', b'k', 0,
+            // mem: []
+            b'[', b']', 0,
+            Op::Brf.op(),
+            Op::Brf.op(),
+        ];
+        let mut vm = VM::new(&data).unwrap();
+
+        vm.stack.push(Obj::Bool(true));
+        vm.stack.push(Obj::Int(0));
+        vm.tick();
+        assert_eq!(1, vm.ip);
+        assert!(vm.stack.empty());
+
+        vm.stack.push(Obj::Bool(false));
+        vm.stack.push(Obj::Int(0));
+        vm.tick();
+        assert_eq!(0, vm.ip);
+        assert_eq!(Some(&2), vm.calls.peek());
+    }
+
+    #[test]
+    fn int_bools() {
+        let data: Vec<u8> = vec![
+            b'R', b'i', b'c', b'k', 0,
+            // mem: [true]
+            b'[', b't', b'r', b'u', b'e', b']', 0,
+            Op::Gth.op(),
+            Op::And.op(),
+            Op::Not.op(),
+            Op::Jmpf.op(),
+            Op::Jmpf.op(),
+        ];
+        let mut vm = VM::new(&data).unwrap();
+        vm.set_int_bools(true);
+        assert_eq!(vec![Obj::Int(1)], vm.mem);
+
+        vm.stack.push(Obj::Int(84));
+        vm.stack.push(Obj::Int(2));
+        vm.tick();
+        assert_eq!(Some(Obj::Int(1)), vm.stack.pop());
+
+        vm.stack.push(Obj::Int(42));
+        vm.stack.push(Obj::Bool(true));
+        vm.tick();
+        assert_eq!(Some(Obj::Int(1)), vm.stack.pop());
+
+        vm.stack.push(Obj::Int(0));
+        vm.tick();
+        assert_eq!(Some(Obj::Int(1)), vm.stack.pop());
+
+        vm.stack.push(Obj::Str(String::from("")));
+        vm.stack.push(Obj::Int(0));
+        vm.tick();
+        assert_eq!(0, vm.ip);
+
+        vm.ip = 4;
+        vm.stack.push(Obj::Null);
+        vm.stack.push(Obj::Int(0));
+        vm.tick();
+        assert!(vm.err.is_some());    // null has no truth value
+    }
+
+    #[test]
+    fn back() {
+        let data: Vec<u8> = vec![
+            b'R', b'i', b'c', b'k', 0,
+            // mem: []
+            b'[', b']', 0,
+            Op::Br.op(),
+            Op::Bac.op(),
+            Op::Bac.op(),
+        ];
+        let mut vm = VM::new(&data).unwrap();
+
+        vm.stack.push(Obj::Int(2));
+        vm.tick();
+        assert_eq!(2, vm.ip);
+        vm.stack.push(Obj::Int(42));    // data left behind by the subroutine
+        vm.tick();
+        assert_eq!(1, vm.ip);
+        assert!(vm.calls.empty());
+        assert_eq!(Some(Obj::Int(42)), vm.stack.pop());
+
+        vm.tick();
+        assert!(vm.err.is_some());    // empty call stack
+    }
+
+    #[test]
+    fn err() {
+        let data: Vec<u8> = vec![
+            b'R', b'i', b'c', b'k', 0,
+            // mem: []
+            b'[', b']', 0,
+            Op::Err.op(),
+            Op::Err.op(),
+            Op::Err.op(),
+        ];
+        let mut vm = VM::new(&data).unwrap();
+
+        vm.stack.push(Obj::Int(3));
+        vm.tick();
+        assert!(!vm.run);
+        assert_eq!(Some(3), vm.exit_code);
+
+        vm.tick();
+        assert!(vm.err.is_some());
+
+        vm.err.take();
+        vm.stack.push(Obj::Int(4294967297));
+        vm.tick();
+        assert_eq!(Some(ErrorKind::InvalidExitCode(4294967297)),
+                   vm.err.take().map(|e| e.kind));
+    }
+
+    #[test]
+    fn float_arithmetic() {
+        let data: Vec<u8> = vec![
+            b'R', b'i', b'c', b'k', 0,
+            // mem: []
+            b'[', b']', 0,
+            Op::Add.op(),
+            Op::Div.op(),
+            Op::Div.op(),
+            Op::Mod.op(),
+            Op::Mul.op(),
+        ];
+        let mut vm = VM::new(&data).unwrap();
+
+        vm.stack.push(Obj::Int(40));
+        vm.stack.push(Obj::Float(2.5));
+        vm.tick();
+        assert_eq!(Some(Obj::Float(42.5)), vm.stack.pop());
+
+        vm.stack.push(Obj::Float(1.0));
+        vm.stack.push(Obj::Int(4));
+        vm.tick();
+        assert_eq!(Some(Obj::Float(0.25)), vm.stack.pop());
+
+        vm.stack.push(Obj::Float(1.0));
+        vm.stack.push(Obj::Float(0.0));
+        vm.tick();
+        assert_eq!(Some(ErrorKind::DivisionByZero),
+                   vm.err.take().map(|e| e.kind));
+
+        vm.stack.push(Obj::Float(5.5));
+        vm.stack.push(Obj::Int(2));
+        vm.tick();
+        assert_eq!(Some(Obj::Float(1.5)), vm.stack.pop());
+
+        vm.stack.push(Obj::Float(f64::MAX));
+        vm.stack.push(Obj::Int(2));
+        vm.tick();
+        assert_eq!(Some(Obj::Float(f64::INFINITY)), vm.stack.pop());
+    }
+
+    #[test]
+    fn float_comparisons() {
+        let data: Vec<u8> = vec![
+            b'R', b'i', b'c', b'k', 0,
+            // mem: []
+            b'[', b']', 0,
+            Op::Gth.op(),
+            Op::Leq.op(),
+            Op::Eq.op(),
+            Op::Neq.op(),
+        ];
+        let mut vm = VM::new(&data).unwrap();
 
-        let mut vm = vm.unwrap();
-        vm.stack.push(Obj::Int(42));
+        vm.stack.push(Obj::Float(2.5));
+        vm.stack.push(Obj::Int(2));
         vm.tick();
-        assert_eq!(Obj::Int(42), vm.mem[0]);
+        assert_eq!(Some(Obj::Bool(true)), vm.stack.pop());
+
+        vm.stack.push(Obj::Float(f64::NAN));
+        vm.stack.push(Obj::Float(1.0));
+        vm.tick();
+        assert_eq!(Some(Obj::Bool(false)), vm.stack.pop());
+
+        vm.stack.push(Obj::Int(2));
+        vm.stack.push(Obj::Float(2.0));
+        vm.tick();
+        assert_eq!(Some(Obj::Bool(true)), vm.stack.pop());
+
+        vm.stack.push(Obj::Float(2.0));
+        vm.stack.push(Obj::Str(String::from("2.0")));
         vm.tick();
-        assert!(vm.err);
+        assert_eq!(Some(Obj::Bool(true)), vm.stack.pop());
     }
 
     #[test]
-    fn drop() {
+    fn itf() {
         let data: Vec<u8> = vec![
             b'R', b'i', b'c', b'k', 0,
             // mem: []
             b'[', b']', 0,
-            Op::Drop.op(),
-            Op::Drop.op(),
+            Op::Itf.op(),
+            Op::Itf.op(),
         ];
-        let vm = VM::new(&data);
-        if let Err(_) = vm {
-            panic!("expected Ok");
-        }
+        let mut vm = VM::new(&data).unwrap();
+        vm.stack.push(Obj::Int(-3));
+        vm.tick();
+        assert_eq!(Some(Obj::Float(-3.0)), vm.stack.pop());
+        vm.stack.push(Obj::Float(1.0));
+        vm.tick();
+        assert!(vm.err.is_some());
+    }
 
-        let mut vm = vm.unwrap();
-        vm.stack.push(Obj::Int(42));
+    #[test]
+    fn fti() {
+        let data: Vec<u8> = vec![
+            b'R', b'i', b'c', b'k', 0,
+            // mem: []
+            b'[', b']', 0,
+            Op::Fti.op(),
+            Op::Fti.op(),
+            Op::Fti.op(),
+            Op::Fti.op(),
+        ];
+        let mut vm = VM::new(&data).unwrap();
+        vm.stack.push(Obj::Float(-3.9));
+        vm.tick();
+        assert_eq!(Some(Obj::Int(-3)), vm.stack.pop());
+        vm.stack.push(Obj::Float(-9223372036854775808.0));
+        vm.tick();
+        assert_eq!(Some(Obj::Int(i64::MIN)), vm.stack.pop());
+        vm.stack.push(Obj::Float(9223372036854775808.0));
+        vm.tick();
+        assert_eq!(Some(Obj::BigInt(BigInt::from(i64::MAX) + 1)), vm.stack.pop());
+        vm.stack.push(Obj::Float(f64::NAN));
+        vm.tick();
+        assert_eq!(Some(ErrorKind::InvalidConversion {
+            value: String::from("NaN"),
+            to: "int",
+        }), vm.err.map(|e| e.kind));
+    }
+
+    #[test]
+    fn stf() {
+        let data: Vec<u8> = vec![
+            b'R', b'i', b'c', b'k', 0,
+            // mem: []
+            b'[', b']', 0,
+            Op::Stf.op(),
+            Op::Stf.op(),
+        ];
+        let mut vm = VM::new(&data).unwrap();
+        vm.stack.push(Obj::Str(String::from("2.5e3")));
+        vm.tick();
+        assert_eq!(Some(Obj::Float(2500.0)), vm.stack.pop());
+        vm.stack.push(Obj::Str(String::from("two")));
+        vm.tick();
+        assert!(vm.err.is_some());
+    }
+
+    #[test]
+    fn fts() {
+        let data: Vec<u8> = vec![
+            b'R', b'i', b'c', b'k', 0,
+            // mem: []
+            b'[', b']', 0,
+            Op::Fts.op(),
+            Op::Fts.op(),
+        ];
+        let mut vm = VM::new(&data).unwrap();
+        vm.stack.push(Obj::Float(3.0));
+        vm.tick();
+        assert_eq!(Some(Obj::Str(String::from("3.0"))), vm.stack.pop());
+        vm.stack.push(Obj::Int(3));
+        vm.tick();
+        assert!(vm.err.is_some());
+    }
+
+    fn list(items: &[i64]) -> Obj {
+        Obj::List(items.iter().map(|i| Obj::Int(*i)).collect())
+    }
+
+    #[test]
+    fn lnew_and_lpush() {
+        let data: Vec<u8> = vec![
+            b'R', b'i', b'c', b'k', 0,
+            // mem: []
+            b'[', b']', 0,
+            Op::Lnew.op(),
+            Op::Lpush.op(),
+            Op::Lpush.op(),
+        ];
+        let mut vm = VM::new(&data).unwrap();
+
+        vm.tick();
+        vm.stack.push(Obj::Int(7));
+        vm.tick();
+        assert_eq!(Some(list(&[7])), vm.stack.pop());
+
+        vm.stack.push(Obj::Int(7));
+        vm.stack.push(Obj::Int(8));
+        vm.tick();
+        assert_eq!(Some(RuntimeError {
+            kind: ErrorKind::TypeMismatch { expected: "list", found: "int" },
+            opcode: Some("lpush"),
+            ip: 2,
+        }), vm.err);
+    }
+
+    #[test]
+    fn lpop() {
+        let data: Vec<u8> = vec![
+            b'R', b'i', b'c', b'k', 0,
+            // mem: []
+            b'[', b']', 0,
+            Op::Lpop.op(),
+            Op::Lpop.op(),
+        ];
+        let mut vm = VM::new(&data).unwrap();
+
+        vm.stack.push(list(&[1, 2]));
+        vm.tick();
+        assert_eq!(Some(Obj::Int(2)), vm.stack.pop());
+        assert_eq!(Some(list(&[1])), vm.stack.pop());
+
+        vm.stack.push(list(&[]));
+        vm.tick();
+        assert_eq!(Some(ErrorKind::EmptyList), vm.err.map(|e| e.kind));
+    }
+
+    #[test]
+    fn lget() {
+        let data: Vec<u8> = vec![
+            b'R', b'i', b'c', b'k', 0,
+            // mem: []
+            b'[', b']', 0,
+            Op::Lget.op(),
+            Op::Lget.op(),
+            Op::Lget.op(),
+        ];
+        let mut vm = VM::new(&data).unwrap();
+
+        vm.stack.push(list(&[4, 5, 6]));
+        vm.stack.push(Obj::Int(1));
         vm.tick();
+        assert_eq!(Some(Obj::Int(5)), vm.stack.pop());
         assert!(vm.stack.empty());
+
+        vm.stack.push(list(&[4, 5, 6]));
+        vm.stack.push(Obj::Int(3));
         vm.tick();
-        assert!(vm.err);
+        assert_eq!(Some(ErrorKind::IndexOutOfBounds { index: 3, len: 3 }),
+                   vm.err.take().map(|e| e.kind));
+
+        vm.stack.push(list(&[4, 5, 6]));
+        vm.stack.push(Obj::Int(-1));
+        vm.tick();
+        assert_eq!(Some(ErrorKind::IndexOutOfBounds { index: -1, len: 3 }),
+                   vm.err.map(|e| e.kind));
     }
 
     #[test]
-    fn sti() {
+    fn lset() {
         let data: Vec<u8> = vec![
             b'R', b'i', b'c', b'k', 0,
             // mem: []
             b'[', b']', 0,
-            Op::Sti.op(),
-            Op::Sti.op(),
+            Op::Lset.op(),
+            Op::Lset.op(),
         ];
-        let vm = VM::new(&data);
-        if let Err(_) = vm {
-            panic!("expected Ok");
-        }
+        let mut vm = VM::new(&data).unwrap();
 
-        let mut vm = vm.unwrap();
-        vm.stack.push(Obj::Str(String::from("42")));
+        vm.stack.push(list(&[4, 5, 6]));
+        vm.stack.push(Obj::Int(0));
+        vm.stack.push(Obj::Str(String::from("four")));
         vm.tick();
-        assert_eq!(Some(Obj::Int(42)), vm.stack.pop());
-        vm.stack.push(Obj::Str(String::from("invalid")));
+        assert_eq!(Some(Obj::List(vec![
+            Obj::Str(String::from("four")), Obj::Int(5), Obj::Int(6),
+        ])), vm.stack.pop());
+
+        vm.stack.push(list(&[]));
+        vm.stack.push(Obj::Int(0));
+        vm.stack.push(Obj::Int(1));
         vm.tick();
-        assert!(vm.err);
+        assert!(vm.err.is_some());
     }
 
     #[test]
-    fn bool() {
+    fn llen() {
         let data: Vec<u8> = vec![
             b'R', b'i', b'c', b'k', 0,
             // mem: []
             b'[', b']', 0,
-            Op::Bool.op(),
-            Op::Bool.op(),
-            Op::Bool.op(),
-            Op::Bool.op(),
-            Op::Bool.op(),
+            Op::Llen.op(),
+            Op::Llen.op(),
         ];
-        let vm = VM::new(&data);
-        if let Err(_) = vm {
-            panic!("expected Ok");
-        }
+        let mut vm = VM::new(&data).unwrap();
 
-        let mut vm = vm.unwrap();
-        
-        vm.stack.push(Obj::Str(String::from("hello world")));
+        vm.stack.push(list(&[4, 5, 6]));
         vm.tick();
-        assert_eq!(Some(Obj::Int(1)), vm.stack.pop());
+        assert_eq!(Some(Obj::Int(3)), vm.stack.pop());
 
-        vm.stack.push(Obj::Str(String::from("")));
+        vm.stack.push(Obj::Str(String::from("456")));
         vm.tick();
-        assert_eq!(Some(Obj::Int(0)), vm.stack.pop());
+        assert!(vm.err.is_some());
+    }
 
-        vm.stack.push(Obj::Int(42));
+    #[test]
+    fn lnext() {
+        let data: Vec<u8> = vec![
+            b'R', b'i', b'c', b'k', 0,
+            // mem: []
+            b'[', b']', 0,
+            Op::Lnext.op(),
+            Op::Lnext.op(),
+            Op::Lnext.op(),
+        ];
+        let mut vm = VM::new(&data).unwrap();
+
+        vm.stack.push(list(&[4]));
+        vm.stack.push(Obj::Int(0));
         vm.tick();
-        assert_eq!(Some(Obj::Int(1)), vm.stack.pop());
+        assert_eq!(&[list(&[4]), Obj::Int(1), Obj::Int(4), Obj::Bool(true)],
+                   vm.stack.as_slice());
+
+        vm.stack.pop();
+        vm.stack.pop();
+        vm.tick();
+        assert_eq!(&[Obj::Bool(false)], vm.stack.as_slice());
+
+        vm.stack.pop();
+        vm.stack.push(list(&[4]));
+        vm.stack.push(Obj::Int(-1));
+        vm.tick();
+        assert!(vm.err.is_some());
+    }
+
+    #[test]
+    fn lists_load_from_memory() {
+        let data: Vec<u8> = vec![
+            b'R', b'i', b'c', b'k', 0,
+            // mem: [[1,[]]]
+            b'[', b'[', b'1', b',', b'[', b']', b']', b']', 0,
+            Op::Push.op(), 0, 0, 0, 0,
+            Op::End.op(),
+        ];
+        let mut vm = VM::new(&data).unwrap();
+
+        vm.tick();
+        assert_eq!(Some(Obj::List(vec![Obj::Int(1), list(&[])])), vm.stack.pop());
+    }
+
+    fn map(fields: &[(&str, i64)]) -> Obj {
+        Obj::Map(fields.iter()
+            .map(|(k, v)| (Key::Str(k.to_string()), Obj::Int(*v)))
+            .collect())
+    }
 
+    #[test]
+    fn mnew_and_mset() {
+        let data: Vec<u8> = vec![
+            b'R', b'i', b'c', b'k', 0,
+            // mem: []
+            b'[', b']', 0,
+            Op::Mnew.op(),
+            Op::Mset.op(),
+            Op::Mset.op(),
+            Op::Mset.op(),
+        ];
+        let mut vm = VM::new(&data).unwrap();
+
+        vm.tick();
+        vm.stack.push(Obj::Str(String::from("a")));
+        vm.stack.push(Obj::Int(1));
+        vm.tick();
+        assert_eq!(Some(map(&[("a", 1)])), vm.stack.peek().cloned());
+
+        vm.stack.push(Obj::Str(String::from("a")));
+        vm.stack.push(Obj::Int(2));
+        vm.tick();
+        assert_eq!(Some(map(&[("a", 2)])), vm.stack.pop());
+
+        vm.stack.push(map(&[]));
+        vm.stack.push(Obj::Float(1.0));
+        vm.stack.push(Obj::Int(2));
+        vm.tick();
+        assert_eq!(Some(ErrorKind::TypeMismatch {
+            expected: "int or str",
+            found: "float",
+        }), vm.err.map(|e| e.kind));
+    }
+
+    #[test]
+    fn mget() {
+        let data: Vec<u8> = vec![
+            b'R', b'i', b'c', b'k', 0,
+            // mem: []
+            b'[', b']', 0,
+            Op::Mget.op(),
+            Op::Mget.op(),
+            Op::Mget.op(),
+        ];
+        let mut vm = VM::new(&data).unwrap();
+
+        vm.stack.push(map(&[("a", 1), ("b", 2)]));
+        vm.stack.push(Obj::Str(String::from("b")));
+        vm.tick();
+        assert_eq!(Some(Obj::Int(2)), vm.stack.pop());
+        assert!(vm.stack.empty());
+
+        vm.stack.push(map(&[("1", 1)]));
+        vm.stack.push(Obj::Int(1));
+        vm.tick();
+        assert_eq!(Some(ErrorKind::KeyNotFound(String::from("1"))),
+                   vm.err.take().map(|e| e.kind));
+
+        vm.stack.push(Obj::List(vec![]));
         vm.stack.push(Obj::Int(0));
         vm.tick();
-        assert_eq!(Some(Obj::Int(0)), vm.stack.pop());
+        assert!(vm.err.is_some());
+    }
+
+    #[test]
+    fn mdel() {
+        let data: Vec<u8> = vec![
+            b'R', b'i', b'c', b'k', 0,
+            // mem: []
+            b'[', b']', 0,
+            Op::Mdel.op(),
+            Op::Mdel.op(),
+        ];
+        let mut vm = VM::new(&data).unwrap();
 
+        vm.stack.push(map(&[("a", 1), ("b", 2)]));
+        vm.stack.push(Obj::Str(String::from("a")));
         vm.tick();
-        assert!(vm.err);
+        assert_eq!(Some(map(&[("b", 2)])), vm.stack.peek().cloned());
+
+        vm.stack.push(Obj::Str(String::from("a")));
+        vm.tick();
+        assert_eq!(Some(map(&[("b", 2)])), vm.stack.pop());
     }
 
     #[test]
-    // In this method we also run destructive tests against binary_int_op works. These tests will
-    // not be run in the rest of the binary operation tests.
-    fn add() {
+    fn mhas() {
         let data: Vec<u8> = vec![
             b'R', b'i', b'c', b'k', 0,
             // mem: []
             b'[', b']', 0,
+            Op::Mhas.op(),
+            Op::Mhas.op(),
+        ];
+        let mut vm = VM::new(&data).unwrap();
+
+        vm.stack.push(map(&[("a", 1)]));
+        vm.stack.push(Obj::Str(String::from("a")));
+        vm.tick();
+        assert_eq!(Some(Obj::Bool(true)), vm.stack.pop());
+
+        vm.stack.push(map(&[("a", 1)]));
+        vm.stack.push(Obj::Str(String::from("b")));
+        vm.tick();
+        assert_eq!(Some(Obj::Bool(false)), vm.stack.pop());
+    }
+
+    #[test]
+    fn mkeys_and_mlen() {
+        let data: Vec<u8> = vec![
+            b'R', b'i', b'c', b'k', 0,
+            // mem: []
+            b'[', b']', 0,
+            Op::Mkeys.op(),
+            Op::Mlen.op(),
+            Op::Mlen.op(),
+        ];
+        let mut vm = VM::new(&data).unwrap();
+
+        let mut fields = Map::new();
+        fields.insert(Key::Str(String::from("b")), Obj::Null);
+        fields.insert(Key::Int(10), Obj::Null);
+        fields.insert(Key::Str(String::from("a")), Obj::Null);
+        vm.stack.push(Obj::Map(fields.clone()));
+        vm.tick();
+        assert_eq!(Some(Obj::List(vec![
+            Obj::Int(10), Obj::Str(String::from("a")), Obj::Str(String::from("b")),
+        ])), vm.stack.pop());
+
+        vm.stack.push(Obj::Map(fields));
+        vm.tick();
+        assert_eq!(Some(Obj::Int(3)), vm.stack.pop());
+
+        vm.stack.push(Obj::List(vec![]));
+        vm.tick();
+        assert!(vm.err.is_some());
+    }
+
+    #[test]
+    fn big_ints() {
+        let data: Vec<u8> = vec![
+            b'R', b'i', b'c', b'k', 0,
+            // mem: []
+            b'[', b']', 0,
+            Op::Sti.op(),
+            Op::Mul.op(),
+            Op::Div.op(),
+            Op::Mod.op(),
+            Op::Lth.op(),
             Op::Add.op(),
-            Op::Add.op(),
-            Op::Add.op(),
+            Op::Itf.op(),
         ];
-        let vm = VM::new(&data);
-        if let Err(_) = vm {
-            panic!("expected Ok");
-        }
+        let mut vm = VM::new(&data).unwrap();
+        let big = |text: &str| Obj::BigInt(text.parse().unwrap());
 
-        let mut vm = vm.unwrap();
+        vm.stack.push(Obj::Str(String::from("100000000000000000000")));
+        vm.tick();
+        assert_eq!(Some(big("100000000000000000000")), vm.stack.peek().cloned());
 
-        vm.stack.push(Obj::Int(40));
-        vm.stack.push(Obj::Int(2));
+        vm.stack.push(big("100000000000000000000"));
         vm.tick();
-        assert_eq!(Some(Obj::Int(42)), vm.stack.pop());
+        assert_eq!(Some(big("10000000000000000000000000000000000000000")),
+                   vm.stack.peek().cloned());
 
-        vm.stack.push(Obj::Int(40));
+        vm.stack.push(big("-100000000000000000000"));
         vm.tick();
-        assert!(vm.err);    // not enough elements for binary pop
+        assert_eq!(Some(big("-100000000000000000000")), vm.stack.pop());
 
-        vm.err = false;
-        vm.stack.push(Obj::Str(String::from("hello world")));
+        vm.stack.push(big("100000000000000000007"));
+        vm.stack.push(Obj::Int(10));
         vm.tick();
-        assert!(vm.err);    // type mismatch
+        assert_eq!(Some(Obj::Int(7)), vm.stack.pop());
+
+        vm.stack.push(Obj::Int(i64::MAX));
+        vm.stack.push(big("9223372036854775808"));
+        vm.tick();
+        assert_eq!(Some(Obj::Bool(true)), vm.stack.pop());
+
+        vm.stack.push(big("9223372036854775808"));
+        vm.stack.push(Obj::Float(0.5));
+        vm.tick();
+        assert_eq!(Some(Obj::Float(9223372036854775808.5)), vm.stack.pop());
+
+        vm.stack.push(big("-9223372036854775809"));
+        vm.tick();
+        assert_eq!(Some(Obj::Float(-9223372036854775808.0)), vm.stack.pop());
+    }
+
+    fn string(s: &str) -> Obj {
+        Obj::Str(String::from(s))
     }
 
     #[test]
-    fn sub() {
+    fn slen() {
         let data: Vec<u8> = vec![
             b'R', b'i', b'c', b'k', 0,
             // mem: []
             b'[', b']', 0,
-            Op::Sub.op(),
-            Op::Sub.op(),
-            Op::Sub.op(),
+            Op::Slen.op(),
+            Op::Slen.op(),
         ];
-        let vm = VM::new(&data);
-        if let Err(_) = vm {
-            panic!("expected Ok");
-        }
+        let mut vm = VM::new(&data).unwrap();
 
-        let mut vm = vm.unwrap();
+        vm.stack.push(string("héllo"));
+        vm.tick();
+        assert_eq!(Some(Obj::Int(5)), vm.stack.pop());
 
-        vm.stack.push(Obj::Int(44));
-        vm.stack.push(Obj::Int(2));
+        vm.stack.push(Obj::Int(5));
         vm.tick();
-        assert_eq!(Some(Obj::Int(42)), vm.stack.pop());
+        assert!(vm.err.is_some());
     }
 
     #[test]
-    fn mul() {
+    fn ssub() {
         let data: Vec<u8> = vec![
             b'R', b'i', b'c', b'k', 0,
             // mem: []
             b'[', b']', 0,
-            Op::Mul.op(),
-            Op::Mul.op(),
-            Op::Mul.op(),
+            Op::Ssub.op(),
+            Op::Ssub.op(),
+            Op::Ssub.op(),
         ];
-        let vm = VM::new(&data);
-        if let Err(_) = vm {
-            panic!("expected Ok");
-        }
+        let mut vm = VM::new(&data).unwrap();
 
-        let mut vm = vm.unwrap();
+        vm.stack.push(string("naïve café"));
+        vm.stack.push(Obj::Int(2));
+        vm.stack.push(Obj::Int(5));
+        vm.tick();
+        assert_eq!(Some(string("ïve")), vm.stack.pop());
 
-        vm.stack.push(Obj::Int(21));
+        vm.stack.push(string("café"));
+        vm.stack.push(Obj::Int(2));
+        vm.stack.push(Obj::Int(5));
+        vm.tick();
+        assert_eq!(Some(ErrorKind::IndexOutOfBounds { index: 5, len: 4 }),
+                   vm.err.take().map(|e| e.kind));
+
+        vm.stack.push(string("café"));
+        vm.stack.push(Obj::Int(3));
         vm.stack.push(Obj::Int(2));
         vm.tick();
-        assert_eq!(Some(Obj::Int(42)), vm.stack.pop());
+        assert_eq!(Some(ErrorKind::IndexOutOfBounds { index: 3, len: 4 }),
+                   vm.err.map(|e| e.kind));
     }
 
     #[test]
-    fn div() {
+    fn sfind() {
         let data: Vec<u8> = vec![
             b'R', b'i', b'c', b'k', 0,
             // mem: []
             b'[', b']', 0,
-            Op::Div.op(),
-            Op::Div.op(),
-            Op::Div.op(),
+            Op::Sfind.op(),
+            Op::Sfind.op(),
         ];
-        let vm = VM::new(&data);
-        if let Err(_) = vm {
-            panic!("expected Ok");
-        }
+        let mut vm = VM::new(&data).unwrap();
 
-        let mut vm = vm.unwrap();
+        vm.stack.push(string("déjà vu"));
+        vm.stack.push(string("vu"));
+        vm.tick();
+        assert_eq!(Some(Obj::Int(5)), vm.stack.pop());
 
-        vm.stack.push(Obj::Int(84));
-        vm.stack.push(Obj::Int(2));
+        vm.stack.push(string("déjà vu"));
+        vm.stack.push(string("jamais"));
         vm.tick();
-        assert_eq!(Some(Obj::Int(42)), vm.stack.pop());
+        assert_eq!(Some(Obj::Int(-1)), vm.stack.pop());
     }
 
     #[test]
-    fn r#mod() {
+    fn srep() {
         let data: Vec<u8> = vec![
             b'R', b'i', b'c', b'k', 0,
             // mem: []
             b'[', b']', 0,
-            Op::Mod.op(),
-            Op::Mod.op(),
-            Op::Mod.op(),
+            Op::Srep.op(),
         ];
-        let vm = VM::new(&data);
-        if let Err(_) = vm {
-            panic!("expected Ok");
-        }
-
-        let mut vm = vm.unwrap();
+        let mut vm = VM::new(&data).unwrap();
 
-        vm.stack.push(Obj::Int(84));
-        vm.stack.push(Obj::Int(2));
+        vm.stack.push(string("a-b-c"));
+        vm.stack.push(string("-"));
+        vm.stack.push(string(", "));
         vm.tick();
-        assert_eq!(Some(Obj::Int(0)), vm.stack.pop());
+        assert_eq!(Some(string("a, b, c")), vm.stack.pop());
     }
 
     #[test]
-    fn gth() {
+    fn ssplit_and_sjoin() {
         let data: Vec<u8> = vec![
             b'R', b'i', b'c', b'k', 0,
             // mem: []
             b'[', b']', 0,
-            Op::Gth.op(),
-            Op::Gth.op(),
-            Op::Gth.op(),
-            Op::Gth.op(),
+            Op::Ssplit.op(),
+            Op::Ssplit.op(),
+            Op::Sjoin.op(),
+            Op::Sjoin.op(),
         ];
-        let vm = VM::new(&data);
-        if let Err(_) = vm {
-            panic!("expected Ok");
-        }
+        let mut vm = VM::new(&data).unwrap();
 
-        let mut vm = vm.unwrap();
+        vm.stack.push(string("a,b,,c"));
+        vm.stack.push(string(","));
+        vm.tick();
+        assert_eq!(Some(Obj::List(vec![
+            string("a"), string("b"), string(""), string("c"),
+        ])), vm.stack.pop());
 
-        vm.stack.push(Obj::Int(84));
-        vm.stack.push(Obj::Int(2));
+        vm.stack.push(string("hé"));
+        vm.stack.push(string(""));
         vm.tick();
-        assert_eq!(Some(Obj::Int(1)), vm.stack.pop());
+        assert_eq!(Some(Obj::List(vec![string("h"), string("é")])),
+                   vm.stack.peek().cloned());
 
-        vm.stack.push(Obj::Int(0));
-        vm.stack.push(Obj::Int(2));
+        vm.stack.push(string("+"));
         vm.tick();
-        assert_eq!(Some(Obj::Int(0)), vm.stack.pop());
+        assert_eq!(Some(string("h+é")), vm.stack.pop());
+
+        vm.stack.push(Obj::List(vec![string("a"), Obj::Int(1)]));
+        vm.stack.push(string("+"));
+        vm.tick();
+        assert_eq!(Some(ErrorKind::TypeMismatch { expected: "str", found: "int" }),
+                   vm.err.map(|e| e.kind));
     }
 
     #[test]
-    fn lth() {
+    fn case_and_trim() {
         let data: Vec<u8> = vec![
             b'R', b'i', b'c', b'k', 0,
             // mem: []
             b'[', b']', 0,
-            Op::Lth.op(),
-            Op::Lth.op(),
-            Op::Lth.op(),
-            Op::Lth.op(),
+            Op::Supper.op(),
+            Op::Slower.op(),
+            Op::Strim.op(),
         ];
-        let vm = VM::new(&data);
-        if let Err(_) = vm {
-            panic!("expected Ok");
-        }
+        let mut vm = VM::new(&data).unwrap();
 
-        let mut vm = vm.unwrap();
+        vm.stack.push(string("straße"));
+        vm.tick();
+        assert_eq!(Some(string("STRASSE")), vm.stack.pop());
 
-        vm.stack.push(Obj::Int(84));
-        vm.stack.push(Obj::Int(2));
+        vm.stack.push(string("ÉTÉ"));
         vm.tick();
-        assert_eq!(Some(Obj::Int(0)), vm.stack.pop());
+        assert_eq!(Some(string("été")), vm.stack.pop());
 
-        vm.stack.push(Obj::Int(0));
-        vm.stack.push(Obj::Int(2));
+        vm.stack.push(string("\t spaced out \n"));
         vm.tick();
-        assert_eq!(Some(Obj::Int(1)), vm.stack.pop());
+        assert_eq!(Some(string("spaced out")), vm.stack.pop());
     }
 
     #[test]
-    fn geq() {
+    fn ord_and_chr() {
         let data: Vec<u8> = vec![
             b'R', b'i', b'c', b'k', 0,
             // mem: []
             b'[', b']', 0,
-            Op::Geq.op(),
-            Op::Geq.op(),
-            Op::Geq.op(),
-            Op::Geq.op(),
-            Op::Geq.op(),
+            Op::Ord.op(),
+            Op::Ord.op(),
+            Op::Chr.op(),
+            Op::Chr.op(),
         ];
-        let vm = VM::new(&data);
-        if let Err(_) = vm {
-            panic!("expected Ok");
-        }
+        let mut vm = VM::new(&data).unwrap();
 
-        let mut vm = vm.unwrap();
+        vm.stack.push(string("€"));
+        vm.tick();
+        assert_eq!(Some(Obj::Int(0x20ac)), vm.stack.pop());
 
-        vm.stack.push(Obj::Int(84));
-        vm.stack.push(Obj::Int(2));
+        vm.stack.push(string("ab"));
         vm.tick();
-        assert_eq!(Some(Obj::Int(1)), vm.stack.pop());
+        assert!(vm.err.take().is_some());
 
-        vm.stack.push(Obj::Int(2));
-        vm.stack.push(Obj::Int(2));
+        vm.stack.push(Obj::Int(0x1f980));
         vm.tick();
-        assert_eq!(Some(Obj::Int(1)), vm.stack.pop());
+        assert_eq!(Some(string("🦀")), vm.stack.pop());
 
-        vm.stack.push(Obj::Int(0));
-        vm.stack.push(Obj::Int(2));
+        vm.stack.push(Obj::Int(0xd800));
         vm.tick();
-        assert_eq!(Some(Obj::Int(0)), vm.stack.pop());
+        assert_eq!(Some(ErrorKind::InvalidConversion {
+            value: String::from("55296"),
+            to: "char",
+        }), vm.err.map(|e| e.kind));
     }
 
     #[test]
-    fn leq() {
+    fn its() {
         let data: Vec<u8> = vec![
             b'R', b'i', b'c', b'k', 0,
             // mem: []
             b'[', b']', 0,
-            Op::Leq.op(),
-            Op::Leq.op(),
-            Op::Leq.op(),
-            Op::Leq.op(),
-            Op::Leq.op(),
+            Op::Its.op(),
+            Op::Its.op(),
+            Op::Its.op(),
         ];
-        let vm = VM::new(&data);
-        if let Err(_) = vm {
-            panic!("expected Ok");
-        }
-
-        let mut vm = vm.unwrap();
+        let mut vm = VM::new(&data).unwrap();
 
-        vm.stack.push(Obj::Int(84));
-        vm.stack.push(Obj::Int(2));
+        vm.stack.push(Obj::Int(-42));
         vm.tick();
-        assert_eq!(Some(Obj::Int(0)), vm.stack.pop());
+        assert_eq!(Some(string("-42")), vm.stack.pop());
 
-        vm.stack.push(Obj::Int(2));
-        vm.stack.push(Obj::Int(2));
+        vm.stack.push(Obj::BigInt(BigInt::from(u64::MAX)));
         vm.tick();
-        assert_eq!(Some(Obj::Int(1)), vm.stack.pop());
+        assert_eq!(Some(string("18446744073709551615")), vm.stack.pop());
 
-        vm.stack.push(Obj::Int(0));
-        vm.stack.push(Obj::Int(2));
+        vm.stack.push(Obj::Float(1.0));
         vm.tick();
-        assert_eq!(Some(Obj::Int(1)), vm.stack.pop());
+        assert!(vm.err.is_some());
     }
 
     #[test]
-    fn and() {
+    fn rtok() {
         let data: Vec<u8> = vec![
             b'R', b'i', b'c', b'k', 0,
             // mem: []
             b'[', b']', 0,
-            Op::And.op(),
-            Op::And.op(),
-            Op::And.op(),
-            Op::And.op(),
+            Op::Rtok.op(),
+            Op::Rtok.op(),
+            Op::Rtok.op(),
         ];
-        let vm = VM::new(&data);
-        if let Err(_) = vm {
-            panic!("expected Ok");
-        }
-
-        let mut vm = vm.unwrap();
-
-        vm.stack.push(Obj::Int(0));
-        vm.stack.push(Obj::Int(0));
-        vm.tick();
-        assert_eq!(Some(Obj::Int(0)), vm.stack.pop());
+        let mut vm = VM::new(&data).unwrap();
+        vm.set_host(Box::new(MemHost::new("  John Smith")));
 
-        vm.stack.push(Obj::Int(0));
-        vm.stack.push(Obj::Int(1));
         vm.tick();
-        assert_eq!(Some(Obj::Int(0)), vm.stack.pop());
+        assert_eq!(&[string("John"), Obj::Bool(true)], vm.stack.as_slice());
 
-        vm.stack.push(Obj::Int(1));
-        vm.stack.push(Obj::Int(0));
+        vm.stack = Stack::new();
         vm.tick();
-        assert_eq!(Some(Obj::Int(0)), vm.stack.pop());
-
-        vm.stack.push(Obj::Int(1));
-        vm.stack.push(Obj::Int(1));
         vm.tick();
-        assert_eq!(Some(Obj::Int(1)), vm.stack.pop());
+        assert_eq!(&[string("Smith"), Obj::Bool(true), Obj::Bool(false)],
+                   vm.stack.as_slice());
     }
 
     #[test]
-    fn or() {
+    fn rline() {
         let data: Vec<u8> = vec![
             b'R', b'i', b'c', b'k', 0,
             // mem: []
             b'[', b']', 0,
-            Op::Or.op(),
-            Op::Or.op(),
-            Op::Or.op(),
-            Op::Or.op(),
+            Op::Rline.op(),
+            Op::Rline.op(),
+            Op::Rline.op(),
         ];
-        let vm = VM::new(&data);
-        if let Err(_) = vm {
-            panic!("expected Ok");
-        }
-
-        let mut vm = vm.unwrap();
-
-        vm.stack.push(Obj::Int(0));
-        vm.stack.push(Obj::Int(0));
-        vm.tick();
-        assert_eq!(Some(Obj::Int(0)), vm.stack.pop());
+        let mut vm = VM::new(&data).unwrap();
+        vm.set_host(Box::new(MemHost::new("John Smith\n\n")));
 
-        vm.stack.push(Obj::Int(0));
-        vm.stack.push(Obj::Int(1));
         vm.tick();
-        assert_eq!(Some(Obj::Int(1)), vm.stack.pop());
+        assert_eq!(Some(Obj::Bool(true)), vm.stack.pop());
+        assert_eq!(Some(string("John Smith")), vm.stack.pop());
 
-        vm.stack.push(Obj::Int(1));
-        vm.stack.push(Obj::Int(0));
         vm.tick();
-        assert_eq!(Some(Obj::Int(1)), vm.stack.pop());
+        assert_eq!(Some(Obj::Bool(true)), vm.stack.pop());
+        assert_eq!(Some(string("")), vm.stack.pop());
 
-        vm.stack.push(Obj::Int(1));
-        vm.stack.push(Obj::Int(1));
         vm.tick();
-        assert_eq!(Some(Obj::Int(1)), vm.stack.pop());
+        assert_eq!(Some(Obj::Bool(false)), vm.stack.pop());
+        assert!(vm.stack.empty());
     }
 
     #[test]
-    fn not() {
+    fn rall() {
         let data: Vec<u8> = vec![
             b'R', b'i', b'c', b'k', 0,
             // mem: []
             b'[', b']', 0,
-            Op::Not.op(),
-            Op::Not.op(),
+            Op::Ins.op(),
+            Op::Rall.op(),
+            Op::Rall.op(),
         ];
-        let vm = VM::new(&data);
-        if let Err(_) = vm {
-            panic!("expected Ok");
-        }
-
-        let mut vm = vm.unwrap();
+        let mut vm = VM::new(&data).unwrap();
+        vm.set_host(Box::new(MemHost::new("first line\nsecond line\n")));
 
-        vm.stack.push(Obj::Int(0));
         vm.tick();
-        assert_eq!(Some(Obj::Int(1)), vm.stack.pop());
-
-        vm.stack.push(Obj::Int(1));
         vm.tick();
-        assert_eq!(Some(Obj::Int(0)), vm.stack.pop());
+        assert_eq!(Some(Obj::Bool(true)), vm.stack.pop());
+        assert_eq!(Some(string("line\nsecond line\n")), vm.stack.pop());
+        vm.tick();
+        assert_eq!(Some(Obj::Bool(false)), vm.stack.pop());
+        assert_eq!(Some(string("first")), vm.stack.pop());
     }
 
     #[test]
-    fn eq() {
+    fn files_need_permission() {
         let data: Vec<u8> = vec![
             b'R', b'i', b'c', b'k', 0,
             // mem: []
             b'[', b']', 0,
-            Op::Eq.op(),
-            Op::Eq.op(),
-            Op::Eq.op(),
+            Op::Fopen.op(),
+            Op::Fexists.op(),
         ];
-        let vm = VM::new(&data);
-        if let Err(_) = vm {
-            panic!("expected Ok");
-        }
-
-        let mut vm = vm.unwrap();
-
-        vm.stack.push(Obj::Str(String::from("let magic = ")));
-        vm.stack.push(Obj::Int(42));
+        let mut vm = VM::new(&data).unwrap();
+        vm.stack.push(string("/tmp/file.txt"));
+        vm.stack.push(string("r"));
         vm.tick();
-        assert_eq!(Some(Obj::Int(0)), vm.stack.pop());
-
-        vm.stack.push(Obj::Str(String::from("hello world")));
-        vm.stack.push(Obj::Str(String::from("hello world")));
-        vm.tick();
-        assert_eq!(Some(Obj::Int(1)), vm.stack.pop());
+        assert_eq!(Some(ErrorKind::PermissionDenied(String::from("/tmp/file.txt"))),
+                   vm.err.take().map(|e| e.kind));
 
-        vm.stack.push(Obj::Int(5));
-        vm.stack.push(Obj::Int(5));
+        vm.stack.push(string("/tmp/file.txt"));
         vm.tick();
-        assert_eq!(Some(Obj::Int(1)), vm.stack.pop());
+        assert_eq!(Some(ErrorKind::PermissionDenied(String::from("/tmp/file.txt"))),
+                   vm.err.take().map(|e| e.kind));
     }
 
     #[test]
-    fn neq() {
+    fn writes_and_reads_files() {
         let data: Vec<u8> = vec![
             b'R', b'i', b'c', b'k', 0,
             // mem: []
             b'[', b']', 0,
-            Op::Neq.op(),
-            Op::Neq.op(),
-            Op::Neq.op(),
+            Op::Fopen.op(),
+            Op::Fwrite.op(),
+            Op::Fclose.op(),
+            Op::Fexists.op(),
+            Op::Fopen.op(),
+            Op::Fline.op(),
+            Op::Fread.op(),
+            Op::Fline.op(),
+            Op::Fclose.op(),
         ];
-        let vm = VM::new(&data);
-        if let Err(_) = vm {
-            panic!("expected Ok");
-        }
+        let mut vm = VM::new(&data).unwrap();
+        let dir = std::env::temp_dir()
+            .join(format!("rick-opcodes-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("file.txt").to_str().unwrap().to_string();
 
-        let mut vm = vm.unwrap();
+        vm.allow_read(&dir).unwrap();
+        vm.allow_write(&dir).unwrap();
 
-        vm.stack.push(Obj::Str(String::from("let magic = ")));
-        vm.stack.push(Obj::Int(42));
+        vm.stack.push(string(&path));
+        vm.stack.push(string("w"));
         vm.tick();
-        assert_eq!(Some(Obj::Int(1)), vm.stack.pop());
+        let file = vm.stack.pop().unwrap();
+        assert_eq!("file", file.type_name());
 
-        vm.stack.push(Obj::Str(String::from("hello world")));
-        vm.stack.push(Obj::Str(String::from("hello world")));
+        vm.stack.push(file.clone());
+        vm.stack.push(list(&[1, 2]));
         vm.tick();
-        assert_eq!(Some(Obj::Int(0)), vm.stack.pop());
+        vm.stack.push(file);
+        vm.tick();
+        assert!(vm.stack.empty());
 
-        vm.stack.push(Obj::Int(5));
-        vm.stack.push(Obj::Int(5));
+        vm.stack.push(string(&path));
         vm.tick();
-        assert_eq!(Some(Obj::Int(0)), vm.stack.pop());
+        assert_eq!(Some(Obj::Bool(true)), vm.stack.pop());
+
+        vm.stack.push(string(&path));
+        vm.stack.push(string("r"));
+        vm.tick();
+        let file = vm.stack.pop().unwrap();
+
+        vm.stack.push(file.clone());
+        vm.tick();
+        assert_eq!(Some(Obj::Bool(true)), vm.stack.pop());
+        assert_eq!(Some(string("[1, 2]")), vm.stack.pop());
+
+        vm.stack.push(file.clone());
+        vm.tick();
+        assert_eq!(Some(string("")), vm.stack.pop());
+
+        vm.stack.push(file.clone());
+        vm.tick();
+        assert_eq!(Some(Obj::Bool(false)), vm.stack.pop());
+
+        vm.stack.push(Obj::Int(0));
+        vm.tick();
+        assert_eq!(Some(ErrorKind::TypeMismatch { expected: "file", found: "int" }),
+                   vm.err.take().map(|e| e.kind));
+        std::fs::remove_dir_all(dir).unwrap();
     }
 }
@@ -1,23 +1,82 @@
+use std::collections::BTreeMap;
 use std::fmt;
 
 extern crate serde_json;
-use serde_json::Value;
+use serde_json::{Number, Value};
+
+extern crate num_bigint;
+use num_bigint::BigInt;
+
+extern crate num_traits;
+use num_traits::{FromPrimitive, ToPrimitive};
 
 use crate::util::TResult;
 
 #[derive(Clone, Debug, PartialEq)]
 pub enum Obj {
     Null,
+    Bool(bool),
     Int(i64),
+    /// An integer outside the range of `Int`. Arithmetic promotes ints to
+    /// big ints when they would overflow, and demotes them again when they
+    /// fit, so the two never overlap.
+    BigInt(BigInt),
+    Float(f64),
     Str(String),
+    List(Vec<Obj>),
+    Map(Map),
+    /// A handle to a file the VM has opened. Handles only exist at run time,
+    /// so they have no JSON or constant pool form.
+    File(usize),
+}
+
+pub type Map = BTreeMap<Key, Obj>;
+
+/// Key is what maps are keyed by. Int keys sort before string keys.
+#[derive(Clone, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Key {
+    Int(i64),
+    Str(String),
+}
+
+impl Key {
+    pub fn from_obj(obj: &Obj) -> Option<Key> {
+        match obj {
+            Obj::Int(i) => Some(Key::Int(*i)),
+            Obj::Str(s) => Some(Key::Str(s.clone())),
+            _ => None,
+        }
+    }
+
+    pub fn to_obj(&self) -> Obj {
+        match self {
+            Key::Int(i) => Obj::Int(*i),
+            Key::Str(s) => Obj::Str(s.clone()),
+        }
+    }
+
+    // JSON object keys are always strings.
+    fn to_json_key(&self) -> String {
+        match self {
+            Key::Int(i) => i.to_string(),
+            Key::Str(s) => s.clone(),
+        }
+    }
 }
 
 impl fmt::Display for Obj {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         match self {
             Obj::Null => write!(f, "null"),
+            Obj::Bool(b) => write!(f, "{}", b),
             Obj::Int(i) => write!(f, "{}", i),
+            Obj::BigInt(b) => write!(f, "{}", b),
+            // Debug formatting keeps the fractional part of whole floats and
+            // switches to exponents for very large and very small ones.
+            Obj::Float(x) => write!(f, "{:?}", x),
             Obj::Str(s) => write!(f, "{}", s),
+            Obj::List(_) | Obj::Map(_) => write!(f, "{}", self.to_literal()),
+            Obj::File(handle) => write!(f, "<file {}>", handle),
         }
    }
 }
@@ -26,24 +85,117 @@ impl Obj {
     pub fn from_json(json_val: &Value) -> TResult<Obj> {
         match json_val {
             Value::Null => Ok(Obj::Null),
-            Value::Number(n) => if n.is_i64() {
-                Ok(Obj::Int(n.as_i64().unwrap()))
+            Value::Number(n) => if let Some(i) = n.as_i64() {
+                Ok(Obj::Int(i))
+            } else if n.is_f64() {
+                Ok(Obj::Float(n.as_f64().unwrap()))
             } else {
-                Err("invalid JSON type used in memory")
+                // Whatever is left is an integer too big for i64, unless it
+                // is a float too big for f64.
+                n.to_string().parse().map(Obj::BigInt)
+                    .map_err(|_| "invalid JSON type used in memory")
             },
             Value::String(s) => Ok(Obj::Str(s.clone())),
-            Value::Bool(b) => Ok(Obj::Int(*b as i64)),
-            _ => Err("invalid JSON type used in memory"),
+            Value::Bool(b) => Ok(Obj::Bool(*b)),
+            Value::Array(vals) => Ok(Obj::List(
+                vals.iter().map(Obj::from_json).collect::<TResult<_>>()?)),
+            Value::Object(fields) => {
+                let mut map = BTreeMap::new();
+                for (key, val) in fields {
+                    map.insert(Key::Str(key.clone()), Obj::from_json(val)?);
+                }
+                Ok(Obj::Map(map))
+            },
         }
     }
 
-    pub fn is_int(&self) -> bool {
+    /// Convert the object to JSON. JSON has no room for infinite or NaN
+    /// floats, so those become null, as do file handles, and int map keys
+    /// become strings.
+    pub fn to_json(&self) -> Value {
         match self {
-            Obj::Int(_) => true,
-            _ => false
+            Obj::Null => Value::Null,
+            Obj::Bool(b) => Value::from(*b),
+            Obj::Int(i) => Value::from(*i),
+            Obj::BigInt(b) => Value::Number(b.to_string().parse::<Number>().unwrap()),
+            Obj::Float(x) => Value::from(*x),
+            Obj::Str(s) => Value::from(s.as_str()),
+            Obj::List(items) => Value::from(
+                items.iter().map(Obj::to_json).collect::<Vec<_>>()),
+            Obj::Map(map) => Value::Object(map.iter()
+                .map(|(key, val)| (key.to_json_key(), val.to_json()))
+                .collect()),
+            Obj::File(_) => Value::Null,
         }
     }
 
+    /// Parse a JSON literal such as `42`, `1.5`, `"hello"`, `true` or `null`. On top
+    /// of JSON, `NaN`, `inf` and `-inf` stand for the special floats.
+    pub fn from_literal(text: &str) -> TResult<Obj> {
+        match text {
+            "NaN" => return Ok(Obj::Float(f64::NAN)),
+            "inf" => return Ok(Obj::Float(f64::INFINITY)),
+            "-inf" => return Ok(Obj::Float(f64::NEG_INFINITY)),
+            _ => (),
+        }
+        match serde_json::from_str::<Value>(text) {
+            Err(_) => Err("invalid literal"),
+            Ok(v) => Obj::from_json(&v),
+        }
+    }
+
+    /// Format the object as a literal that `from_literal` accepts. File
+    /// handles have no literal and keep their display form.
+    pub fn to_literal(&self) -> String {
+        match self {
+            Obj::Float(x) if !x.is_finite() => format!("{:?}", x),
+            Obj::File(_) => self.to_string(),
+            Obj::List(items) => {
+                let items: Vec<String> = items.iter().map(Obj::to_literal).collect();
+                format!("[{}]", items.join(", "))
+            },
+            Obj::Map(map) => {
+                let fields: Vec<String> = map.iter()
+                    .map(|(key, val)| format!("{}: {}",
+                                              Value::from(key.to_json_key()),
+                                              val.to_literal()))
+                    .collect();
+                format!("{{{}}}", fields.join(", "))
+            },
+            _ => self.to_json().to_string(),
+        }
+    }
+
+    /// Whether the object is or holds a map with int keys, which JSON can't
+    /// tell apart from string keys.
+    pub fn has_int_keys(&self) -> bool {
+        match self {
+            Obj::List(items) => items.iter().any(Obj::has_int_keys),
+            Obj::Map(map) => map.iter().any(|(key, val)| {
+                matches!(key, Key::Int(_)) || val.has_int_keys()
+            }),
+            _ => false,
+        }
+    }
+
+    pub fn type_name(&self) -> &'static str {
+        match self {
+            Obj::Null => "null",
+            Obj::Bool(_) => "bool",
+            Obj::Int(_) => "int",
+            Obj::BigInt(_) => "bigint",
+            Obj::Float(_) => "float",
+            Obj::Str(_) => "str",
+            Obj::List(_) => "list",
+            Obj::Map(_) => "map",
+            Obj::File(_) => "file",
+        }
+    }
+
+    pub fn is_int(&self) -> bool {
+        matches!(self, Obj::Int(_))
+    }
+
     pub fn as_int(&self) -> Option<i64> {
         match self {
             Obj::Int(i) => Some(*i),
@@ -51,19 +203,189 @@ impl Obj {
         }
     }
 
-    pub fn to_bool(&self) -> Option<Obj> {
+    /// Wrap an integer of any size, as an `Int` if it fits.
+    pub fn from_bigint(b: BigInt) -> Obj {
+        match b.to_i64() {
+            Some(i) => Obj::Int(i),
+            None => Obj::BigInt(b),
+        }
+    }
+
+    /// Parse a decimal integer of any size.
+    pub fn parse_int(text: &str) -> Option<Obj> {
+        match text.parse::<i64>() {
+            Ok(i) => Some(Obj::Int(i)),
+            Err(_) => text.parse::<BigInt>().ok().map(Obj::from_bigint),
+        }
+    }
+
+    /// Value of ints of either size as a big int.
+    pub fn as_bigint(&self) -> Option<BigInt> {
+        match self {
+            Obj::Int(i) => Some(BigInt::from(*i)),
+            Obj::BigInt(b) => Some(b.clone()),
+            _ => None
+        }
+    }
+
+    /// Numeric value of ints and floats, with ints promoted to floats.
+    pub fn as_float(&self) -> Option<f64> {
+        match self {
+            Obj::Int(i) => Some(*i as f64),
+            Obj::BigInt(b) => b.to_f64(),
+            Obj::Float(x) => Some(*x),
+            _ => None
+        }
+    }
+
+    /// Truthiness of the object: zero, empty strings and empty collections
+    /// are false, and null has no truth value at all.
+    pub fn to_bool(&self) -> Option<bool> {
         match self {
-            Obj::Int(i) => Some(Obj::Int((*i != 0) as i64)),
-            Obj::Str(s) => Some(Obj::Int((s.len() != 0) as i64)),
+            Obj::Bool(b) => Some(*b),
+            Obj::Int(i) => Some(*i != 0),
+            Obj::BigInt(_) => Some(true),
+            Obj::Float(x) => Some(*x != 0.0),
+            Obj::Str(s) => Some(!s.is_empty()),
+            Obj::List(items) => Some(!items.is_empty()),
+            Obj::Map(map) => Some(!map.is_empty()),
+            Obj::File(_) => Some(true),
             Obj::Null => None,
         }
     }
 
+    /// Compare objects by value. Ints and floats are both numbers, so they
+    /// compare equal when the float is exactly that whole number.
     pub fn equal(&self, other: &Obj) -> bool {
         match (self, other) {
+            (Obj::Bool(a), Obj::Bool(b)) => a == b,
             (Obj::Int(i), Obj::Int(j)) => i == j,
+            (Obj::BigInt(a), Obj::BigInt(b)) => a == b,
             (Obj::Str(s), Obj::Str(t)) => s == t,
+            (Obj::File(a), Obj::File(b)) => a == b,
+            (Obj::List(a), Obj::List(b)) =>
+                a.len() == b.len() && a.iter().zip(b).all(|(x, y)| x.equal(y)),
+            (Obj::Map(a), Obj::Map(b)) =>
+                a.len() == b.len() && a.iter().zip(b)
+                    .all(|((k, x), (l, y))| k == l && x.equal(y)),
+            (Obj::Float(x), Obj::Float(y)) => x == y,
+            (Obj::Float(x), Obj::Int(_) | Obj::BigInt(_)) =>
+                int_equals_float(other, *x),
+            (Obj::Int(_) | Obj::BigInt(_), Obj::Float(x)) =>
+                int_equals_float(self, *x),
             _ => false,
         }
     }
 }
+
+// Promoting the int to a float would round ints beyond 2^53, so the float is
+// turned into an int instead, if it is a whole number at all.
+fn int_equals_float(int: &Obj, x: f64) -> bool {
+    x.fract() == 0.0 && BigInt::from_f64(x) == int.as_bigint()
+}
+
+#[cfg(test)]
+mod obj_tests {
+    use super::*;
+
+    #[test]
+    fn parses_float_literals() {
+        assert_eq!(Ok(Obj::Float(1.5)), Obj::from_literal("1.5"));
+        assert_eq!(Ok(Obj::Float(1.0)), Obj::from_literal("1.0"));
+        assert_eq!(Ok(Obj::Int(1)), Obj::from_literal("1"));
+        assert_eq!(Ok(Obj::Float(f64::NEG_INFINITY)), Obj::from_literal("-inf"));
+    }
+
+    #[test]
+    fn formats_floats() {
+        assert_eq!("3.0", Obj::Float(3.0).to_string());
+        assert_eq!("0.1", Obj::Float(0.1).to_string());
+        assert_eq!("1e100", Obj::Float(1e100).to_string());
+        assert_eq!("NaN", Obj::Float(f64::NAN).to_literal());
+        assert_eq!("-2.5", Obj::Float(-2.5).to_literal());
+        assert_eq!(Value::Null, Obj::Float(f64::INFINITY).to_json());
+    }
+
+    #[test]
+    fn compares_numbers_across_types() {
+        assert!(Obj::Int(2).equal(&Obj::Float(2.0)));
+        assert!(!Obj::Float(2.5).equal(&Obj::Int(2)));
+        assert!(!Obj::Float(f64::NAN).equal(&Obj::Float(f64::NAN)));
+        assert!(!Obj::Float(0.0).equal(&Obj::Str(String::from("0"))));
+
+        // 2^53 + 1 rounds to 2^53 as a float, but isn't equal to it.
+        let two_53 = 9_007_199_254_740_992.0;
+        assert!(Obj::Int(1 << 53).equal(&Obj::Float(two_53)));
+        assert!(!Obj::Int((1 << 53) + 1).equal(&Obj::Float(two_53)));
+        let big: BigInt = BigInt::from(1) << 70;
+        let float = Obj::Float(2f64.powi(70));
+        assert!(Obj::BigInt(big.clone()).equal(&float));
+        assert!(!float.equal(&Obj::BigInt(big + 1)));
+        assert!(!Obj::Int(0).equal(&Obj::Float(f64::INFINITY)));
+    }
+
+    #[test]
+    fn nests_lists() {
+        let list = Obj::from_literal(r#"[1, "a", [2.5, false]]"#).unwrap();
+        assert_eq!(Obj::List(vec![
+            Obj::Int(1),
+            Obj::Str(String::from("a")),
+            Obj::List(vec![Obj::Float(2.5), Obj::Bool(false)]),
+        ]), list);
+        assert_eq!(r#"[1, "a", [2.5, false]]"#, list.to_string());
+        assert_eq!(Ok(list.clone()), Obj::from_literal(&list.to_literal()));
+        assert!(list.equal(&Obj::from_literal(r#"[1.0, "a", [2.5, false]]"#).unwrap()));
+        assert!(!list.equal(&Obj::from_literal(r#"[1, "a"]"#).unwrap()));
+        assert_eq!(Some(false), Obj::List(vec![]).to_bool());
+    }
+
+    #[test]
+    fn maps_keys_to_values() {
+        let map = Obj::from_literal(r#"{"b": [1], "a": 2.5}"#).unwrap();
+        let mut expect = BTreeMap::new();
+        expect.insert(Key::Str(String::from("a")), Obj::Float(2.5));
+        expect.insert(Key::Str(String::from("b")), Obj::List(vec![Obj::Int(1)]));
+        assert_eq!(Obj::Map(expect.clone()), map);
+        assert_eq!(r#"{"a": 2.5, "b": [1]}"#, map.to_string());
+        assert_eq!(Ok(map.clone()), Obj::from_literal(&map.to_literal()));
+
+        assert!(map.equal(&Obj::from_literal(r#"{"a": 2.5, "b": [1.0]}"#).unwrap()));
+        assert!(!map.equal(&Obj::from_literal(r#"{"a": 2.5, "c": [1]}"#).unwrap()));
+        assert!(!map.equal(&Obj::from_literal(r#"{"a": 2.5}"#).unwrap()));
+
+        assert!(!map.has_int_keys());
+        expect.insert(Key::Int(1), Obj::Null);
+        let map = Obj::Map(expect);
+        assert_eq!(r#"{"1": null, "a": 2.5, "b": [1]}"#, map.to_literal());
+        assert!(Obj::List(vec![map]).has_int_keys());
+    }
+
+    #[test]
+    fn holds_integers_of_any_size() {
+        let big = "123456789012345678901234567890";
+        let obj = Obj::from_literal(big).unwrap();
+        assert_eq!(Obj::BigInt(big.parse().unwrap()), obj);
+        assert_eq!(big, obj.to_string());
+        assert_eq!(big, obj.to_literal());
+        assert_eq!(Ok(Obj::BigInt(BigInt::from(u64::MAX))),
+                   Obj::from_literal("18446744073709551615"));
+        assert_eq!(Ok(Obj::Int(i64::MIN)), Obj::from_literal("-9223372036854775808"));
+        assert!(Obj::from_literal("1e400").is_err());
+
+        assert_eq!(Some(Obj::Int(-42)), Obj::parse_int("-42"));
+        assert_eq!(Some(obj.clone()), Obj::parse_int(big));
+        assert_eq!(None, Obj::parse_int("12e3"));
+        assert_eq!(Obj::Int(7), Obj::from_bigint(BigInt::from(7)));
+        assert!(!obj.equal(&Obj::Float(1.2345678901234568e29)));
+    }
+
+    #[test]
+    fn keeps_bools_apart_from_ints() {
+        assert_eq!(Ok(Obj::Bool(true)), Obj::from_literal("true"));
+        assert_eq!("false", Obj::Bool(false).to_string());
+        assert_eq!("true", Obj::Bool(true).to_literal());
+        assert!(Obj::Bool(true).equal(&Obj::Bool(true)));
+        assert!(!Obj::Bool(true).equal(&Obj::Int(1)));
+        assert!(!Obj::Bool(false).equal(&Obj::Float(0.0)));
+    }
+}
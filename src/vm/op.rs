@@ -3,37 +3,86 @@
 use super::VM;
 
 pub struct Opcode {
+    pub name: &'static str,
     pub opcode_method: fn(&mut VM),
     pub operand_offset: usize,
 }
 
 /// INSTRUCTION_SET contains opcode instruction data for each available opcode
 /// in the VM.
-pub const INSTRUCTION_SET: [Opcode; 24] = [
-    Opcode { opcode_method: VM::end, operand_offset: 0 },
-    Opcode { opcode_method: VM::push, operand_offset: 4 },
-    Opcode { opcode_method: VM::pop, operand_offset: 4 },
-    Opcode { opcode_method: VM::drop, operand_offset: 0 },
-    Opcode { opcode_method: VM::ini, operand_offset: 0 },
-    Opcode { opcode_method: VM::ins, operand_offset: 0 },
-    Opcode { opcode_method: VM::out, operand_offset: 0 },
-    Opcode { opcode_method: VM::nl, operand_offset: 0 },
-    Opcode { opcode_method: VM::sti, operand_offset: 0 },
-    Opcode { opcode_method: VM::bool, operand_offset: 0 },
-    Opcode { opcode_method: VM::add, operand_offset: 0 },
-    Opcode { opcode_method: VM::sub, operand_offset: 0 },
-    Opcode { opcode_method: VM::mul, operand_offset: 0 },
-    Opcode { opcode_method: VM::div, operand_offset: 0 },
-    Opcode { opcode_method: VM::r#mod, operand_offset: 0 },
-    Opcode { opcode_method: VM::gth, operand_offset: 0 },
-    Opcode { opcode_method: VM::lth, operand_offset: 0 },
-    Opcode { opcode_method: VM::geq, operand_offset: 0 },
-    Opcode { opcode_method: VM::leq, operand_offset: 0 },
-    Opcode { opcode_method: VM::and, operand_offset: 0 },
-    Opcode { opcode_method: VM::or, operand_offset: 0 },
-    Opcode { opcode_method: VM::not, operand_offset: 0 },
-    Opcode { opcode_method: VM::eq, operand_offset: 0 },
-    Opcode { opcode_method: VM::neq, operand_offset: 0 },
+pub const INSTRUCTION_SET: [Opcode; 72] = [
+    Opcode { name: "end", opcode_method: VM::end, operand_offset: 0 },
+    Opcode { name: "push", opcode_method: VM::push, operand_offset: 4 },
+    Opcode { name: "pop", opcode_method: VM::pop, operand_offset: 4 },
+    Opcode { name: "drop", opcode_method: VM::drop, operand_offset: 0 },
+    Opcode { name: "ini", opcode_method: VM::ini, operand_offset: 0 },
+    Opcode { name: "ins", opcode_method: VM::ins, operand_offset: 0 },
+    Opcode { name: "out", opcode_method: VM::out, operand_offset: 0 },
+    Opcode { name: "nl", opcode_method: VM::nl, operand_offset: 0 },
+    Opcode { name: "sti", opcode_method: VM::sti, operand_offset: 0 },
+    Opcode { name: "bool", opcode_method: VM::bool, operand_offset: 0 },
+    Opcode { name: "add", opcode_method: VM::add, operand_offset: 0 },
+    Opcode { name: "sub", opcode_method: VM::sub, operand_offset: 0 },
+    Opcode { name: "mul", opcode_method: VM::mul, operand_offset: 0 },
+    Opcode { name: "div", opcode_method: VM::div, operand_offset: 0 },
+    Opcode { name: "mod", opcode_method: VM::r#mod, operand_offset: 0 },
+    Opcode { name: "gth", opcode_method: VM::gth, operand_offset: 0 },
+    Opcode { name: "lth", opcode_method: VM::lth, operand_offset: 0 },
+    Opcode { name: "geq", opcode_method: VM::geq, operand_offset: 0 },
+    Opcode { name: "leq", opcode_method: VM::leq, operand_offset: 0 },
+    Opcode { name: "and", opcode_method: VM::and, operand_offset: 0 },
+    Opcode { name: "or", opcode_method: VM::or, operand_offset: 0 },
+    Opcode { name: "not", opcode_method: VM::not, operand_offset: 0 },
+    Opcode { name: "eq", opcode_method: VM::eq, operand_offset: 0 },
+    Opcode { name: "neq", opcode_method: VM::neq, operand_offset: 0 },
+    Opcode { name: "con", opcode_method: VM::con, operand_offset: 0 },
+    Opcode { name: "jump", opcode_method: VM::jump, operand_offset: 0 },
+    Opcode { name: "jmpt", opcode_method: VM::jmpt, operand_offset: 0 },
+    Opcode { name: "jmpf", opcode_method: VM::jmpf, operand_offset: 0 },
+    Opcode { name: "br", opcode_method: VM::br, operand_offset: 0 },
+    Opcode { name: "brt", opcode_method: VM::brt, operand_offset: 0 },
+    Opcode { name: "brf", opcode_method: VM::brf, operand_offset: 0 },
+    Opcode { name: "back", opcode_method: VM::back, operand_offset: 0 },
+    Opcode { name: "err", opcode_method: VM::err, operand_offset: 0 },
+    Opcode { name: "itf", opcode_method: VM::itf, operand_offset: 0 },
+    Opcode { name: "fti", opcode_method: VM::fti, operand_offset: 0 },
+    Opcode { name: "stf", opcode_method: VM::stf, operand_offset: 0 },
+    Opcode { name: "fts", opcode_method: VM::fts, operand_offset: 0 },
+    Opcode { name: "lnew", opcode_method: VM::lnew, operand_offset: 0 },
+    Opcode { name: "lpush", opcode_method: VM::lpush, operand_offset: 0 },
+    Opcode { name: "lpop", opcode_method: VM::lpop, operand_offset: 0 },
+    Opcode { name: "lget", opcode_method: VM::lget, operand_offset: 0 },
+    Opcode { name: "lset", opcode_method: VM::lset, operand_offset: 0 },
+    Opcode { name: "llen", opcode_method: VM::llen, operand_offset: 0 },
+    Opcode { name: "lnext", opcode_method: VM::lnext, operand_offset: 0 },
+    Opcode { name: "mnew", opcode_method: VM::mnew, operand_offset: 0 },
+    Opcode { name: "mset", opcode_method: VM::mset, operand_offset: 0 },
+    Opcode { name: "mget", opcode_method: VM::mget, operand_offset: 0 },
+    Opcode { name: "mdel", opcode_method: VM::mdel, operand_offset: 0 },
+    Opcode { name: "mhas", opcode_method: VM::mhas, operand_offset: 0 },
+    Opcode { name: "mkeys", opcode_method: VM::mkeys, operand_offset: 0 },
+    Opcode { name: "mlen", opcode_method: VM::mlen, operand_offset: 0 },
+    Opcode { name: "slen", opcode_method: VM::slen, operand_offset: 0 },
+    Opcode { name: "ssub", opcode_method: VM::ssub, operand_offset: 0 },
+    Opcode { name: "sfind", opcode_method: VM::sfind, operand_offset: 0 },
+    Opcode { name: "srep", opcode_method: VM::srep, operand_offset: 0 },
+    Opcode { name: "ssplit", opcode_method: VM::ssplit, operand_offset: 0 },
+    Opcode { name: "sjoin", opcode_method: VM::sjoin, operand_offset: 0 },
+    Opcode { name: "supper", opcode_method: VM::supper, operand_offset: 0 },
+    Opcode { name: "slower", opcode_method: VM::slower, operand_offset: 0 },
+    Opcode { name: "strim", opcode_method: VM::strim, operand_offset: 0 },
+    Opcode { name: "ord", opcode_method: VM::ord, operand_offset: 0 },
+    Opcode { name: "chr", opcode_method: VM::chr, operand_offset: 0 },
+    Opcode { name: "its", opcode_method: VM::its, operand_offset: 0 },
+    Opcode { name: "rtok", opcode_method: VM::rtok, operand_offset: 0 },
+    Opcode { name: "rline", opcode_method: VM::rline, operand_offset: 0 },
+    Opcode { name: "rall", opcode_method: VM::rall, operand_offset: 0 },
+    Opcode { name: "fopen", opcode_method: VM::fopen, operand_offset: 0 },
+    Opcode { name: "fread", opcode_method: VM::fread, operand_offset: 0 },
+    Opcode { name: "fline", opcode_method: VM::fline, operand_offset: 0 },
+    Opcode { name: "fwrite", opcode_method: VM::fwrite, operand_offset: 0 },
+    Opcode { name: "fclose", opcode_method: VM::fclose, operand_offset: 0 },
+    Opcode { name: "fexists", opcode_method: VM::fexists, operand_offset: 0 },
 ];
 
 /// This C-like enum is used to create versatile opcode tests that don't need
@@ -72,6 +121,45 @@ pub enum Op {
     Brf,
     Bac,
     Err,
+    Itf,
+    Fti,
+    Stf,
+    Fts,
+    Lnew,
+    Lpush,
+    Lpop,
+    Lget,
+    Lset,
+    Llen,
+    Lnext,
+    Mnew,
+    Mset,
+    Mget,
+    Mdel,
+    Mhas,
+    Mkeys,
+    Mlen,
+    Slen,
+    Ssub,
+    Sfind,
+    Srep,
+    Ssplit,
+    Sjoin,
+    Supper,
+    Slower,
+    Strim,
+    Ord,
+    Chr,
+    Its,
+    Rtok,
+    Rline,
+    Rall,
+    Fopen,
+    Fread,
+    Fline,
+    Fwrite,
+    Fclose,
+    Fexists,
 }
 
 impl Op {
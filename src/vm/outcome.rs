@@ -0,0 +1,39 @@
+use super::error::RuntimeError;
+
+/// Outcome describes how a VM run came to a halt.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Outcome {
+    /// Program reached the `end` opcode.
+    End,
+    /// Program executed the `err` opcode with the given exit code.
+    Exit(i32),
+    /// Program was stopped by a runtime fault.
+    Fault(RuntimeError),
+}
+
+impl Outcome {
+    pub fn exit_code(&self) -> i32 {
+        match self {
+            Outcome::End => 0,
+            Outcome::Exit(code) => *code,
+            Outcome::Fault(_) => 1,
+        }
+    }
+}
+
+#[cfg(test)]
+mod outcome_tests {
+    use super::*;
+    use super::super::error::ErrorKind;
+
+    #[test]
+    fn exit_code() {
+        assert_eq!(0, Outcome::End.exit_code());
+        assert_eq!(3, Outcome::Exit(3).exit_code());
+        assert_eq!(1, Outcome::Fault(RuntimeError {
+            kind: ErrorKind::UnknownOpcode(0x7e),
+            opcode: None,
+            ip: 0,
+        }).exit_code());
+    }
+}
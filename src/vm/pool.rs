@@ -0,0 +1,307 @@
+use std::collections::BTreeMap;
+use std::convert::TryInto;
+
+extern crate num_bigint;
+use num_bigint::BigInt;
+
+use super::error::LoadError;
+use super::obj::{Key, Obj};
+
+type LoadResult<T> = Result<T, LoadError>;
+type EncodeResult<T> = Result<T, &'static str>;
+
+const NULL: u8 = 0;
+const INT: u8 = 1;
+const STR: u8 = 2;
+const FLOAT: u8 = 3;
+const BOOL: u8 = 4;
+const LIST: u8 = 5;
+const MAP: u8 = 6;
+const BIGINT: u8 = 7;
+
+// Lists and maps nest and decoding recurses, so hostile input must not be
+// allowed to go too deep.
+const MAX_DEPTH: usize = 128;
+
+/// Encode memory as a binary constant pool: the number of constants as a
+/// varint, then each constant as a type tag followed by its value. Ints are
+/// zigzag varints, big ints a varint byte length followed by the number in
+/// big-endian two's complement, floats are their 8 IEEE 754 bytes in
+/// big-endian order, bools a single 0 or 1 byte, strings a varint byte length
+/// followed by UTF-8, lists their length as a varint followed by their
+/// elements and maps their size followed by each key (an int or a string) and
+/// its value.
+pub fn encode(mem: &[Obj]) -> EncodeResult<Vec<u8>> {
+    let mut data = Vec::new();
+    write_varint(&mut data, mem.len() as u64);
+    for obj in mem {
+        write_obj(&mut data, obj)?;
+    }
+    Ok(data)
+}
+
+fn write_obj(data: &mut Vec<u8>, obj: &Obj) -> EncodeResult<()> {
+    match obj {
+        Obj::Null => data.push(NULL),
+        // A handle means nothing outside the run that opened it.
+        Obj::File(_) => return Err("file handles can't be stored as constants"),
+        Obj::Bool(b) => data.extend_from_slice(&[BOOL, *b as u8]),
+        Obj::Int(i) => {
+            data.push(INT);
+            write_varint(data, zigzag(*i));
+        },
+        Obj::BigInt(b) => {
+            let bytes = b.to_signed_bytes_be();
+            data.push(BIGINT);
+            write_varint(data, bytes.len() as u64);
+            data.extend_from_slice(&bytes);
+        },
+        Obj::Float(x) => {
+            data.push(FLOAT);
+            data.extend_from_slice(&x.to_be_bytes());
+        },
+        Obj::Str(s) => {
+            data.push(STR);
+            write_varint(data, s.len() as u64);
+            data.extend_from_slice(s.as_bytes());
+        },
+        Obj::List(items) => {
+            data.push(LIST);
+            write_varint(data, items.len() as u64);
+            for item in items {
+                write_obj(data, item)?;
+            }
+        },
+        Obj::Map(map) => {
+            data.push(MAP);
+            write_varint(data, map.len() as u64);
+            for (key, val) in map {
+                write_obj(data, &key.to_obj())?;
+                write_obj(data, val)?;
+            }
+        },
+    }
+    Ok(())
+}
+
+/// Decode a binary constant pool. Error offsets are relative to `data`.
+pub fn decode(data: &[u8]) -> LoadResult<Vec<Obj>> {
+    let mut reader = Reader { data, pos: 0 };
+    let len = reader.varint()?;
+    let mut mem = Vec::new();
+    for _ in 0..len {
+        mem.push(reader.obj(0)?);
+    }
+    if reader.pos != data.len() {
+        return Err(LoadError::at(reader.pos,
+                                 "trailing bytes in constant pool"));
+    }
+    Ok(mem)
+}
+
+struct Reader<'a> {
+    data: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn obj(&mut self, depth: usize) -> LoadResult<Obj> {
+        let tag_pos = self.pos;
+        let obj = match self.byte()? {
+            NULL => Obj::Null,
+            BOOL => match self.byte()? {
+                0 => Obj::Bool(false),
+                1 => Obj::Bool(true),
+                _ => return Err(LoadError::at(self.pos - 1,
+                                              "invalid bool in constant pool")),
+            },
+            INT => Obj::Int(unzigzag(self.varint()?)),
+            BIGINT => {
+                let len = self.varint()? as usize;
+                Obj::from_bigint(BigInt::from_signed_bytes_be(self.bytes(len)?))
+            },
+            FLOAT => Obj::Float(f64::from_be_bytes(
+                self.bytes(8)?.try_into().unwrap())),
+            STR => {
+                let len = self.varint()? as usize;
+                let start = self.pos;
+                let bytes = self.bytes(len)?;
+                match String::from_utf8(bytes.to_vec()) {
+                    Err(e) => return Err(LoadError::at(
+                        start + e.utf8_error().valid_up_to(),
+                        "invalid UTF-8 in constant pool")),
+                    Ok(s) => Obj::Str(s),
+                }
+            },
+            LIST | MAP if depth >= MAX_DEPTH => return Err(LoadError::at(
+                tag_pos, "constants nested too deeply in constant pool")),
+            LIST => {
+                let len = self.varint()?;
+                let mut items = Vec::new();
+                for _ in 0..len {
+                    items.push(self.obj(depth + 1)?);
+                }
+                Obj::List(items)
+            },
+            MAP => {
+                let len = self.varint()?;
+                let mut map = BTreeMap::new();
+                for _ in 0..len {
+                    let key_pos = self.pos;
+                    let key = match Key::from_obj(&self.obj(depth + 1)?) {
+                        None => return Err(LoadError::at(
+                            key_pos, "invalid map key in constant pool")),
+                        Some(key) if map.contains_key(&key) => return Err(
+                            LoadError::at(key_pos,
+                                          "duplicate map key in constant pool")),
+                        Some(key) => key,
+                    };
+                    map.insert(key, self.obj(depth + 1)?);
+                }
+                Obj::Map(map)
+            },
+            _ => return Err(LoadError::at(tag_pos,
+                                          "unknown constant type tag")),
+        };
+        Ok(obj)
+    }
+
+    fn byte(&mut self) -> LoadResult<u8> {
+        Ok(self.bytes(1)?[0])
+    }
+
+    fn bytes(&mut self, len: usize) -> LoadResult<&'a [u8]> {
+        let end = self.pos.checked_add(len)
+            .filter(|end| *end <= self.data.len());
+        match end {
+            None => Err(LoadError::at(self.data.len(),
+                                      "truncated constant pool")),
+            Some(end) => {
+                let bytes = &self.data[self.pos..end];
+                self.pos = end;
+                Ok(bytes)
+            },
+        }
+    }
+
+    // LEB128: seven bits per byte, least significant first, high bit set on
+    // all but the last byte.
+    fn varint(&mut self) -> LoadResult<u64> {
+        let start = self.pos;
+        let too_long =
+            || LoadError::at(start, "varint too long in constant pool");
+        let mut value: u64 = 0;
+        for shift in (0..64).step_by(7) {
+            let byte = self.byte()?;
+            let bits = (byte & 0x7f) as u64;
+            if shift == 63 && bits > 1 {
+                return Err(too_long());
+            }
+            value |= bits << shift;
+            if byte & 0x80 == 0 {
+                return Ok(value);
+            }
+        }
+        Err(too_long())
+    }
+}
+
+fn write_varint(data: &mut Vec<u8>, mut value: u64) {
+    while value >= 0x80 {
+        data.push((value as u8 & 0x7f) | 0x80);
+        value >>= 7;
+    }
+    data.push(value as u8);
+}
+
+// Zigzag encoding keeps small negative numbers short: 0, -1, 1, -2, ...
+// become 0, 1, 2, 3, ...
+fn zigzag(i: i64) -> u64 {
+    ((i << 1) ^ (i >> 63)) as u64
+}
+
+fn unzigzag(u: u64) -> i64 {
+    ((u >> 1) as i64) ^ -((u & 1) as i64)
+}
+
+#[cfg(test)]
+mod pool_tests {
+    use super::*;
+
+    #[test]
+    fn encodes_constants() {
+        let mem = vec![
+            Obj::Null,
+            Obj::Int(-1),
+            Obj::Int(300),
+            Obj::Str(String::from("hé")),
+        ];
+        assert_eq!(Ok(vec![
+            4,
+            NULL,
+            INT, 1,
+            INT, 0xd8, 0x04,
+            STR, 3, b'h', 0xc3, 0xa9,
+        ]), encode(&mem));
+        assert_eq!(Err("file handles can't be stored as constants"),
+                   encode(&[Obj::List(vec![Obj::File(0)])]));
+    }
+
+    #[test]
+    fn round_trips_constants() {
+        let mem = vec![
+            Obj::Int(i64::MIN),
+            Obj::Int(i64::MAX),
+            Obj::BigInt(BigInt::from(i64::MAX) + 1),
+            Obj::BigInt(-BigInt::from(u64::MAX) * 1000),
+            Obj::Int(0),
+            Obj::Str(String::new()),
+            Obj::Str(String::from("nul\0inside")),
+            Obj::Null,
+            Obj::Float(-0.5),
+            Obj::Float(f64::INFINITY),
+            Obj::Bool(true),
+            Obj::Bool(false),
+            Obj::List(vec![]),
+            Obj::List(vec![Obj::Int(1), Obj::List(vec![Obj::Null])]),
+            Obj::Map(BTreeMap::new()),
+            Obj::Map(vec![
+                (Key::Int(-1), Obj::Str(String::from("minus one"))),
+                (Key::Str(String::from("-1")), Obj::Map(BTreeMap::new())),
+            ].into_iter().collect()),
+        ];
+        assert_eq!(Ok(mem.clone()), decode(&encode(&mem).unwrap()));
+        // Big ints that fit an int are ints.
+        assert_eq!(Ok(vec![Obj::Int(-1)]), decode(&[1, BIGINT, 1, 0xff]));
+    }
+
+    #[test]
+    fn rejects_malformed_pools() {
+        let err = |offset, msg| Err(LoadError::at(offset, msg));
+        assert_eq!(err(0, "truncated constant pool"), decode(&[]));
+        assert_eq!(err(3, "truncated constant pool"), decode(&[1, INT, 0x80]));
+        assert_eq!(err(4, "truncated constant pool"),
+                   decode(&[1, STR, 5, b'a']));
+        assert_eq!(err(1, "unknown constant type tag"), decode(&[1, 9]));
+        assert_eq!(err(4, "invalid UTF-8 in constant pool"),
+                   decode(&[1, STR, 2, b'a', 0xff]));
+        assert_eq!(err(2, "invalid bool in constant pool"),
+                   decode(&[1, BOOL, 2]));
+        assert_eq!(err(3, "truncated constant pool"), decode(&[1, LIST, 1]));
+        let mut deep = vec![1];
+        deep.extend([LIST, 1].repeat(MAX_DEPTH + 1));
+        assert_eq!(err(2 * MAX_DEPTH + 1,
+                       "constants nested too deeply in constant pool"),
+                   decode(&deep));
+        assert_eq!(err(3, "invalid map key in constant pool"),
+                   decode(&[1, MAP, 1, NULL, NULL]));
+        assert_eq!(err(6, "duplicate map key in constant pool"),
+                   decode(&[1, MAP, 2, INT, 2, NULL, INT, 2, NULL]));
+        assert_eq!(err(4, "truncated constant pool"),
+                   decode(&[1, BIGINT, 9, 1]));
+        assert_eq!(err(1, "trailing bytes in constant pool"), decode(&[0, 0]));
+        assert_eq!(err(2, "varint too long in constant pool"),
+                   decode(&[1, INT, 0xff, 0xff, 0xff, 0xff, 0xff,
+                            0xff, 0xff, 0xff, 0xff, 0x7f]));
+    }
+}
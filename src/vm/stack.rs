@@ -29,6 +29,15 @@ impl<T> Stack<T> {
         self.items.last()
     }
 
+    pub fn as_slice(&self) -> &[T] {
+        &self.items
+    }
+
+    /// Return up to `n` items from the top of the stack, topmost last.
+    pub fn top(&self, n: usize) -> &[T] {
+        &self.items[self.items.len().saturating_sub(n)..]
+    }
+
     pub fn len(&self) -> usize {
         self.items.len()
     }
@@ -47,7 +56,7 @@ mod stack_tests {
         let mut st = Stack::new();
         st.push(21);
         assert_eq!(&21, st.peek().unwrap());
-        assert_eq!(false, st.empty());
+        assert!(!st.empty());
     }
 
     #[test]
@@ -64,6 +73,13 @@ mod stack_tests {
         assert_eq!(&6.3, st.peek().unwrap());
     }
 
+    #[test]
+    fn top() {
+        let st = Stack::from(vec![1, 2, 3]);
+        assert_eq!(&[2, 3], st.top(2));
+        assert_eq!(&[1, 2, 3], st.top(5));
+    }
+
     #[test]
     fn len() {
         let st: Stack<i32> = Stack::new();
@@ -73,7 +89,7 @@ mod stack_tests {
     #[test]
     fn empty() {
         let st: Stack<i32> = Stack::new();
-        assert_eq!(true, st.empty());
+        assert!(st.empty());
     }
 
     #[test]
@@ -0,0 +1,111 @@
+use std::io::Write;
+use std::ops::RangeInclusive;
+
+use super::VM;
+use super::obj::Obj;
+use super::op::INSTRUCTION_SET;
+
+/// Tracer logs every executed instruction that passes its filters: the
+/// instruction's offset, mnemonic and operand, followed by the top of the
+/// stack after execution and, optionally, the memory slot written by `pop`.
+/// Programs built with debug info get variable names instead of slot numbers
+/// and the source position of each instruction.
+pub struct Tracer {
+    out: Box<dyn Write>,
+    ips: Option<RangeInclusive<usize>>,
+    opcodes: Vec<String>,
+    mem_writes: bool,
+    depth: usize,
+}
+
+impl Tracer {
+    pub fn new(out: Box<dyn Write>) -> Self {
+        Self {
+            out,
+            ips: None,
+            opcodes: Vec::new(),
+            mem_writes: false,
+            depth: 3,
+        }
+    }
+
+    /// Only log instructions whose offset falls into `ips`.
+    pub fn set_ip_range(&mut self, ips: RangeInclusive<usize>) {
+        self.ips = Some(ips);
+    }
+
+    /// Only log instructions with one of the given mnemonics. Fails on
+    /// mnemonics that aren't in the instruction set, which would never match.
+    pub fn set_opcodes(&mut self, opcodes: Vec<String>) -> Result<(), String> {
+        if let Some(unknown) = opcodes.iter()
+            .find(|o| !INSTRUCTION_SET.iter().any(|i| i.name == o.as_str())) {
+            return Err(format!("unknown opcode {:?}", unknown));
+        }
+        self.opcodes = opcodes;
+        Ok(())
+    }
+
+    pub fn set_mem_writes(&mut self, mem_writes: bool) {
+        self.mem_writes = mem_writes;
+    }
+
+    /// Set how many values from the top of the stack each line shows.
+    pub fn set_depth(&mut self, depth: usize) {
+        self.depth = depth;
+    }
+
+    pub(super) fn log(&mut self, vm: &VM) {
+        let ip = vm.instruction_start;
+        let opcode = &INSTRUCTION_SET[vm.opcode as usize];
+        if !self.accepts(ip, opcode.name) {
+            return;
+        }
+
+        let mp = vm.operand as usize;
+        let operand = match opcode.operand_offset {
+            0 => String::new(),
+            _ => slot(vm, mp),
+        };
+        let mut line = format!("#{:<6} {:<5} {:<8} {}",
+                               ip, opcode.name, operand, self.snapshot(vm));
+
+        if self.mem_writes && opcode.name == "pop" && vm.err.is_none() {
+            line.push_str(&format!("  {} <- {}", slot(vm, mp),
+                                   vm.mem[mp].to_literal()));
+        }
+        if let Some(pos) = vm.debug_info().and_then(|d| d.position(ip)) {
+            line.push_str(&format!("  @ {}", pos));
+        }
+
+        // A broken trace sink shouldn't take the traced program down with it.
+        writeln!(self.out, "{}", line).ok();
+    }
+
+    fn accepts(&self, ip: usize, name: &str) -> bool {
+        let ip_ok = match &self.ips {
+            None => true,
+            Some(ips) => ips.contains(&ip),
+        };
+        let op_ok = self.opcodes.is_empty()
+            || self.opcodes.iter().any(|o| o == name);
+        ip_ok && op_ok
+    }
+
+    fn snapshot(&self, vm: &VM) -> String {
+        let top = vm.stack.top(self.depth);
+        let mut values: Vec<String> = top.iter()
+            .map(Obj::to_literal)
+            .collect();
+        if top.len() < vm.stack.len() {
+            values.insert(0, String::from(".."));
+        }
+        format!("[{}]", values.join(", "))
+    }
+}
+
+fn slot(vm: &VM, mp: usize) -> String {
+    match vm.debug_info().and_then(|d| d.name(mp)) {
+        Some(name) => name.to_string(),
+        None => format!("m{}", mp),
+    }
+}
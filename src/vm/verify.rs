@@ -0,0 +1,205 @@
+use std::collections::HashSet;
+use std::fmt;
+
+use super::error::ErrorKind;
+use super::obj::Obj;
+use super::op::{INSTRUCTION_SET, Op};
+
+/// Problem is a defect found by `verify` at the given instruction offset.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Problem {
+    pub offset: usize,
+    pub kind: ErrorKind,
+}
+
+impl fmt::Display for Problem {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "#{}: {}", self.offset, self.kind)
+    }
+}
+
+/// Walk every instruction once and collect all problems that would otherwise
+/// only surface mid-run: unknown opcodes, truncated operands, memory operands
+/// out of bounds, and jump targets that don't land on an instruction.
+///
+/// Jump targets live on the stack, so only the ones pushed from a memory
+/// slot right before a jump or branch can be checked statically, and only
+/// when no `pop` anywhere in the program writes to that slot.
+pub fn verify(mem: &[Obj], instructions: &[u8]) -> Result<(), Vec<Problem>> {
+    let mut problems = Vec::new();
+    let mut starts = HashSet::new();
+    let mut targets = Vec::new();
+    let mut written = HashSet::new();
+    let mut last_push: Option<u32> = None;
+
+    let mut ip = 0;
+    while ip < instructions.len() {
+        let offset = ip;
+        let opcode = instructions[ip];
+        starts.insert(offset);
+        ip += 1;
+
+        let instruction = match INSTRUCTION_SET.get(opcode as usize) {
+            None => {
+                problems.push(Problem {
+                    offset,
+                    kind: ErrorKind::UnknownOpcode(opcode),
+                });
+                last_push = None;
+                continue;
+            },
+            Some(instruction) => instruction,
+        };
+
+        let operand = match instruction.operand_offset {
+            0 => None,
+            n if ip + n > instructions.len() => {
+                problems.push(Problem {
+                    offset,
+                    kind: ErrorKind::TruncatedOperand,
+                });
+                break;
+            },
+            n => {
+                let mut bytes = [0; 4];
+                bytes.copy_from_slice(&instructions[ip..ip + n]);
+                ip += n;
+                Some(u32::from_be_bytes(bytes))
+            },
+        };
+
+        if let Some(mp) = operand {
+            let mp = mp as usize;
+            if mp >= mem.len() {
+                problems.push(Problem {
+                    offset,
+                    kind: ErrorKind::MemoryOutOfBounds { mp, len: mem.len() },
+                });
+            }
+        }
+
+        if is_jump(opcode) {
+            if let Some(mp) = last_push {
+                targets.push((offset, mp as usize));
+            }
+        }
+        if opcode == Op::Pop.op() {
+            written.extend(operand.map(|mp| mp as usize));
+        }
+
+        last_push = if opcode == Op::Push.op() { operand } else { None };
+    }
+
+    for (offset, mp) in targets {
+        let target = match mem.get(mp) {
+            Some(Obj::Int(target)) if !written.contains(&mp) => *target,
+            _ => continue,
+        };
+        if target < 0 || !starts.contains(&(target as usize)) {
+            problems.push(Problem {
+                offset,
+                kind: ErrorKind::InvalidCodeLocation(target),
+            });
+        }
+    }
+
+    if problems.is_empty() {
+        Ok(())
+    } else {
+        problems.sort_by_key(|p| p.offset);
+        Err(problems)
+    }
+}
+
+fn is_jump(opcode: u8) -> bool {
+    [Op::Jum.op(), Op::Jmpt.op(), Op::Jmpf.op(),
+     Op::Br.op(), Op::Brt.op(), Op::Brf.op()].contains(&opcode)
+}
+
+#[cfg(test)]
+mod verify_tests {
+    use super::*;
+
+    #[test]
+    fn accepts_valid_program() {
+        let mem = vec![Obj::Int(5), Obj::Null];
+        let instructions = vec![
+            Op::Push.op(), 0, 0, 0, 0,
+            Op::Jum.op(),
+            Op::Pop.op(), 0, 0, 0, 1,
+            Op::End.op(),
+        ];
+        assert_eq!(Ok(()), verify(&mem, &instructions));
+    }
+
+    #[test]
+    fn rejects_unknown_opcode() {
+        let instructions = vec![Op::Nl.op(), b'~', Op::End.op()];
+        assert_eq!(Err(vec![Problem {
+            offset: 1,
+            kind: ErrorKind::UnknownOpcode(b'~'),
+        }]), verify(&[], &instructions));
+    }
+
+    #[test]
+    fn rejects_truncated_operand() {
+        let instructions = vec![Op::Push.op(), 0, 0];
+        assert_eq!(Err(vec![Problem {
+            offset: 0,
+            kind: ErrorKind::TruncatedOperand,
+        }]), verify(&[Obj::Null], &instructions));
+    }
+
+    #[test]
+    fn rejects_memory_pointer_out_of_bounds() {
+        let instructions = vec![
+            Op::Push.op(), 0, 0, 0, 0,
+            Op::Pop.op(), 0, 0, 0, 1,
+        ];
+        assert_eq!(Err(vec![Problem {
+            offset: 5,
+            kind: ErrorKind::MemoryOutOfBounds { mp: 1, len: 1 },
+        }]), verify(&[Obj::Null], &instructions));
+    }
+
+    #[test]
+    fn rejects_jump_into_operand() {
+        let mem = vec![Obj::Int(1)];
+        let instructions = vec![
+            Op::Push.op(), 0, 0, 0, 0,
+            Op::Br.op(),
+        ];
+        assert_eq!(Err(vec![Problem {
+            offset: 5,
+            kind: ErrorKind::InvalidCodeLocation(1),
+        }]), verify(&mem, &instructions));
+    }
+
+    #[test]
+    fn skips_jump_targets_in_variables() {
+        // The slot starts out as a bad target, but holds a good one by the
+        // time the jump runs.
+        let mem = vec![Obj::Int(1), Obj::Int(0)];
+        let instructions = vec![
+            Op::Push.op(), 0, 0, 0, 1,
+            Op::Pop.op(), 0, 0, 0, 0,
+            Op::Push.op(), 0, 0, 0, 0,
+            Op::Jum.op(),
+        ];
+        assert_eq!(Ok(()), verify(&mem, &instructions));
+    }
+
+    #[test]
+    fn reports_all_problems() {
+        let mem = vec![Obj::Int(-1)];
+        let instructions = vec![
+            b'~',
+            Op::Push.op(), 0, 0, 0, 0,
+            Op::Jmpt.op(),
+            Op::Pop.op(), 0, 0, 0, 7,
+        ];
+        let problems = verify(&mem, &instructions).unwrap_err();
+        assert_eq!(vec![0, 6, 7],
+                   problems.iter().map(|p| p.offset).collect::<Vec<_>>());
+    }
+}
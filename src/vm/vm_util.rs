@@ -1,52 +1,122 @@
 extern crate serde_json;
 use serde_json as sj;
 use serde_json::Value;
+use serde_json::value::RawValue;
+
+use std::collections::BTreeMap;
 
-use crate::util::TResult;
 use super::obj::Obj;
+use super::debug_info::DebugInfo;
+use super::error::LoadError;
+
+type LoadResult<T> = Result<T, LoadError>;
 
-pub fn watermark_ok(bytecode: &Vec<u8>) -> bool {
+pub fn watermark_ok(bytecode: &[u8]) -> bool {
     bytecode.starts_with("Rick\0".as_bytes())
 }
 
-pub fn read_mem(bytecode: &Vec<u8>) -> TResult<Vec<Obj>> {
-    // As per specification, watermark "Rick\0" is 5 bytes long, therefore,
-    // we start reading JSON mem data at index 5.
-    let mut end: usize = 5;
-    while bytecode[end] != b'\0' {
-        end += 1;
+// As per specification, watermark "Rick\0" is 5 bytes long, therefore, the
+// header starts at index 5 and runs up to the next NUL byte.
+const HEADER_START: usize = 5;
+
+fn header_end(bytecode: &[u8]) -> LoadResult<usize> {
+    let header = bytecode.get(HEADER_START..).unwrap_or(&[]);
+    match header.iter().position(|b| *b == b'\0') {
+        None => Err(LoadError::at(bytecode.len(),
+                                  "memory header is not NUL-terminated")),
+        Some(len) => Ok(HEADER_START + len),
     }
+}
 
-    let vals: sj::Result<Vec<Value>> = sj::from_slice(&bytecode[5..end]);
-    match vals {
-        Err(e) => {
-            println!("{}", e);
-            Err("invalid memory value")
-        },
-        Ok(v) => Ok(json_into_obj(v)?),
+// The header is either the bare JSON memory array or an object holding it
+// under "mem" alongside optional "debug" info.
+fn read_header(bytecode: &[u8]) -> LoadResult<Value> {
+    let end = header_end(bytecode)?;
+    parse_json(&bytecode[HEADER_START..end], "invalid memory header")
+        .map_err(|e| e.shifted(HEADER_START))
+}
+
+/// Parse JSON, pointing errors at the offending byte of `data`.
+pub fn parse_json(data: &[u8], what: &str) -> LoadResult<Value> {
+    sj::from_slice(data).map_err(|e| {
+        let line_start: usize = data.split(|b| *b == b'\n')
+            .take(e.line().saturating_sub(1))
+            .map(|line| line.len() + 1)
+            .sum();
+        let offset = (line_start + e.column().saturating_sub(1)).min(data.len());
+        // serde_json appends its own line and column, which the offset
+        // replaces.
+        let msg = e.to_string();
+        let msg = msg.split(" at line ").next().unwrap_or("");
+        LoadError::at(offset, format!("{}: {}", what, msg))
+    })
+}
+
+pub fn read_mem(bytecode: &[u8]) -> LoadResult<Vec<Obj>> {
+    let header = read_header(bytecode)?;
+    let data = &bytecode[HEADER_START..header_end(bytecode)?];
+    // Values are read again from their own text, so that errors can point at
+    // the offending one.
+    let (vals, text) = match &header {
+        Value::Object(fields) => (fields.get("mem"), field(data, "mem")),
+        _ => (Some(&header), Some(data)),
+    };
+    match (vals.map(Value::is_array), text) {
+        (Some(true), Some(text)) => json_into_obj(text)
+            .map_err(|e| e.shifted(HEADER_START + offset_in(data, text))),
+        _ => Err(LoadError::at(HEADER_START,
+                               "memory header is not a JSON array")),
     }
 }
 
-fn json_into_obj(json_vals: Vec<Value>) -> TResult<Vec<Obj>> {
+// The text of field `name` of the JSON object `data`.
+fn field<'a>(data: &'a [u8], name: &str) -> Option<&'a [u8]> {
+    let fields: BTreeMap<String, &RawValue> = sj::from_slice(data).ok()?;
+    fields.get(name).map(|raw| raw.get().as_bytes())
+}
+
+// Offset at which `inner`, a slice of `outer`, starts.
+fn offset_in(outer: &[u8], inner: &[u8]) -> usize {
+    inner.as_ptr() as usize - outer.as_ptr() as usize
+}
+
+pub fn read_debug_info(bytecode: &[u8]) -> LoadResult<Option<DebugInfo>> {
+    match read_header(bytecode)?.get("debug") {
+        None => Ok(None),
+        Some(debug) => DebugInfo::from_json(debug)
+            .map(Some)
+            .map_err(|e| LoadError::at(HEADER_START, e)),
+    }
+}
+
+/// Turn the JSON array `data` into memory objects. Errors name the offending
+/// slot and point at its value.
+pub fn json_into_obj(data: &[u8]) -> LoadResult<Vec<Obj>> {
+    let vals: Vec<&RawValue> = sj::from_slice(data)
+        .map_err(|_| LoadError::at(0, "memory is not a JSON array"))?;
     let mut objects: Vec<Obj> = Vec::new();
 
-    for val in json_vals.iter() {
-        objects.push(Obj::from_json(val)?);
+    for (mp, raw) in vals.iter().enumerate() {
+        let at = offset_in(data, raw.get().as_bytes());
+        let obj = sj::from_str(raw.get())
+            .map_err(|_| "invalid JSON value")
+            .and_then(|val| Obj::from_json(&val));
+        match obj {
+            Err(e) => return Err(LoadError::at(at, format!("{} (slot {})",
+                                                           e, mp))),
+            Ok(obj) => objects.push(obj),
+        }
     }
 
     Ok(objects)
 }
 
-pub fn read_instructions(bytecode: &Vec<u8>) -> TResult<Vec<u8>> {
-    let mut start: usize = 5;
-    while bytecode[start] != b'\0' {
-        start += 1;
-    }
-    start += 1;
+pub fn read_instructions(bytecode: &[u8]) -> LoadResult<Vec<u8>> {
+    let start = header_end(bytecode)? + 1;
 
     let instructions = bytecode[start..].to_vec();
     match instructions.len() {
-        0 => Err("empty instructions list"),
+        0 => Err(LoadError::at(start, "empty instructions list")),
         _ => Ok(instructions)
     }
 }
@@ -70,15 +140,15 @@ mod vm_util_tests {
     #[test]
     fn fails_on_wrong_data_format() {
         let data = "Rick\0{}\0\0".as_bytes().to_vec();
-        if let Ok(_) = read_mem(&data) {
+        if read_mem(&data).is_ok() {
             panic!("expected Err");
         }
     }
 
     #[test]
     fn fails_for_unexpected_values() {
-        let data = "Rick\0[[\"hello\", 32], 42]\0\0".as_bytes().to_vec();
-        if let Ok(_) = read_mem(&data) {
+        let data = "Rick\0[1e400, 42]\0\0".as_bytes().to_vec();
+        if read_mem(&data).is_ok() {
             panic!("expected Err");
         }
     }
@@ -93,15 +163,69 @@ mod vm_util_tests {
                 Obj::Str(String::from("magic")),
                 Obj::Int(42),
                 Obj::Null,
-                Obj::Int(1),
+                Obj::Bool(true),
             ]),
         }
     }
 
+    #[test]
+    fn reads_mem_and_debug_info_from_object_header() {
+        let data = "Rick\0{\"mem\": [42], \"debug\": {\"names\": {\"0\": \"answer\"}}}\0\0"
+            .as_bytes().to_vec();
+        assert_eq!(Ok(vec![Obj::Int(42)]), read_mem(&data));
+        let info = read_debug_info(&data).unwrap().unwrap();
+        assert_eq!(Some("answer"), info.name(0));
+    }
+
+    #[test]
+    fn debug_info_is_optional() {
+        let data = "Rick\0[42]\0\0".as_bytes().to_vec();
+        assert_eq!(Ok(None), read_debug_info(&data));
+        let data = "Rick\0{\"mem\": []}\0\0".as_bytes().to_vec();
+        assert_eq!(Ok(None), read_debug_info(&data));
+    }
+
     #[test]
     fn fails_with_no_instructions() {
         let data = "Rick\0[]\0".as_bytes().to_vec();
-        assert_eq!(Err("empty instructions list"), read_instructions(&data));
+        assert_eq!(Err(LoadError::at(8, "empty instructions list")),
+                   read_instructions(&data));
+    }
+
+    #[test]
+    fn fails_on_unterminated_header() {
+        let data = "Rick\0[1,2".as_bytes().to_vec();
+        let err = LoadError::at(9, "memory header is not NUL-terminated");
+        assert_eq!(Err(err.clone()), read_mem(&data));
+        assert_eq!(Err(err), read_instructions(&data));
+        assert!(read_mem(b"Rick").is_err());
+    }
+
+    #[test]
+    fn points_at_bad_json() {
+        let data = "Rick\0[1,\n 2 x]\0\0".as_bytes().to_vec();
+        match read_mem(&data) {
+            Err(LoadError::Malformed { offset, msg }) => {
+                assert_eq!(12, offset);
+                assert_eq!("invalid memory header: expected `,` or `]`", msg);
+            },
+            other => panic!("expected a malformed header, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn names_bad_memory_slots() {
+        let headers = [("Rick\0[1, 1e400]\0\0", 9),
+                       ("Rick\0{\"mem\": [1, 1e400]}\0\0", 17)];
+        for (data, at) in &headers {
+            match read_mem(data.as_bytes()) {
+                Err(LoadError::Malformed { offset, msg }) => {
+                    assert_eq!(*at, offset);
+                    assert!(msg.ends_with("(slot 1)"));
+                },
+                other => panic!("expected a malformed header, got {:?}", other),
+            }
+        }
     }
 
     #[test]